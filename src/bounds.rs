@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::SimulationConfig;
+use crate::dataset::Dataset;
+
+/// Offline lower bounds on the makespan (`total_cycle`) of a dataset on a chip. No scheduler,
+/// even one knowing all requests in advance, can finish all jobs earlier than these bounds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LowerBounds {
+    /// The chip can execute at most `size_x * size_y` blocks per cycle, so all blocks requested
+    /// at or after `t` need `ceil(volume / (size_x * size_y))` cycles after `t`. This is the
+    /// maximum over all request times `t`.
+    pub volume: u64,
+    /// The longest burst time of a job.
+    pub longest_job: u64,
+    /// The latest completion time when each job starts as soon as it is requested.
+    pub arrival_plus_burst: u64,
+    /// The maximum of the bounds above.
+    pub makespan: u64,
+}
+
+impl LowerBounds {
+    pub fn new(config: &SimulationConfig, dataset: &Dataset) -> Self {
+        let area = config.size_x as u64 * config.size_y as u64;
+        let mut requests: Vec<_> = dataset
+            .requests()
+            .into_iter()
+            .map(|(req, program)| (req.time, program.volume(), program.burst_time()))
+            .collect();
+        // Sort by the request time in descending order to accumulate the volume of the
+        // requests at or after each time.
+        requests.sort_by_key(|r| std::cmp::Reverse(r.0));
+
+        let mut volume = 0;
+        let mut volume_after = 0;
+        for (i, (time, v, _)) in requests.iter().enumerate() {
+            volume_after += v;
+            // Only the earliest of the requests at the same time gives the bound.
+            if requests.get(i + 1).is_none_or(|next| next.0 != *time) {
+                volume = volume.max(time + volume_after.div_ceil(area));
+            }
+        }
+        let longest_job = requests.iter().map(|(_, _, b)| *b).max().unwrap_or(0);
+        let arrival_plus_burst = requests.iter().map(|(t, _, b)| t + b).max().unwrap_or(0);
+
+        Self {
+            volume,
+            longest_job,
+            arrival_plus_burst,
+            makespan: volume.max(longest_job).max(arrival_plus_burst),
+        }
+    }
+
+    /// Returns the ratio of the achieved makespan to the lower bound.
+    pub fn ratio(&self, makespan: u64) -> Option<f64> {
+        (self.makespan > 0).then(|| makespan as f64 / self.makespan as f64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LowerBounds;
+    use crate::config::SimulationConfig;
+    use crate::dataset::Dataset;
+    use crate::test_utils;
+
+    #[test]
+    fn test_lower_bounds() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.size_x = 2;
+        config.size_y = 2;
+        // a 2x2x2 cuboid and a 1x1x3 polycube
+        let json = r#"{
+            "programs": [
+                {"Cuboid": [{"pos": [0, 0, 0], "size_x": 2, "size_y": 2, "size_z": 2}]},
+                {"Polycube": {"blocks": [[0, 0, 0], [0, 0, 1], [0, 0, 2]]}}
+            ],
+            "job_requests": [[0, 0], [0, 0], [5, 1], [1, 0]]
+        }"#;
+        let dataset: Dataset = serde_json::from_str(json).unwrap();
+        let bounds = LowerBounds::new(&config, &dataset);
+
+        // 3 cuboids of 8 blocks and a polycube of 3 blocks on 4 tiles
+        assert_eq!(bounds.volume, 7);
+        assert_eq!(bounds.longest_job, 3);
+        assert_eq!(bounds.arrival_plus_burst, 8);
+        assert_eq!(bounds.makespan, 8);
+        assert_eq!(bounds.ratio(10), Some(1.25));
+    }
+}
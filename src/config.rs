@@ -1,9 +1,17 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use crate::error::QMPError;
 use crate::preprocess::PreprocessKind;
-use crate::scheduler::SchedulerKind;
+use crate::scheduler::batching::AdaptiveBatchConfig;
+use crate::scheduler::latency::LatencyPredictorConfig;
+use crate::scheduler::lp_dump::ModelFormat;
+use crate::scheduler::lp_scheduler::{LPObjective, LPSolver};
+use crate::scheduler::queue::{validate_tenant_shares, QueuePolicy};
+use crate::scheduler::timing::{SchedulingCostModel, SchedulingTimeMode};
+use crate::scheduler::SchedulerParams;
 
 /// TODO: Support non-rectangle chip?
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,15 +23,63 @@ pub struct SimulationConfig {
     pub no_output_program: bool,
     pub enable_defrag: bool,
     pub defrag_interval: Option<u64>,
+    /// How the latency of each scheduling is measured (`wall-clock` by default)
+    pub scheduling_time: Option<SchedulingTimeMode>,
+    /// The cost model of the `modelled` scheduling time
+    pub scheduling_cost: Option<SchedulingCostModel>,
+    /// Delays each scheduling to collect a fuller batch (a scheduling starts as soon as possible
+    /// by default)
+    pub batching_window: Option<BatchingWindow>,
     pub preprocessor: PreprocessorConfig,
     pub scheduler: SchedulerConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SchedulerConfig {
-    pub kind: SchedulerKind,
+    /// The name of the scheduler registered in `SchedulerRegistry`
+    pub kind: String,
     pub time_limit: Option<u32>,
+    /// The maximum number of jobs scheduled at once (the initial one if `adaptive_batch` is set)
     pub batch_size: Option<u32>,
+    /// Adapts the batch size to the queue length and the scheduling latency
+    pub adaptive_batch: Option<AdaptiveBatchConfig>,
+    /// The policy to decide which waiting jobs enter each batch (`priority` by default)
+    pub queue_policy: Option<QueuePolicy>,
+    /// The share of each tenant used by the `fair-share` queue policy (1 if not listed)
+    pub tenant_shares: Option<BTreeMap<String, f64>>,
+    /// The predictor of the scheduling latency, which decides the scheduling point (the average
+    /// of the past latencies by default)
+    pub latency_predictor: Option<LatencyPredictorConfig>,
+    /// The number of threads to evaluate placement candidates (1 by default, 0 for the number
+    /// of CPUs). The result does not depend on it.
+    pub num_threads: Option<usize>,
+    /// The seed of the random number generator used by randomized schedulers
+    pub seed: Option<u64>,
+    /// The maximum number of iterations of the annealing scheduler in each scheduling
+    pub annealing_iterations: Option<u32>,
+    /// The number of partial placements kept in each step of the beam search scheduler
+    pub beam_width: Option<u32>,
+    /// The objective function of the LP scheduler (`makespan` by default)
+    pub lp_objective: Option<LPObjective>,
+    /// The solver backend of the LP scheduler (`cbc` by default, or `minilp` without the `cbc`
+    /// feature)
+    pub solver: Option<LPSolver>,
+    /// The directory where the MILP model of each scheduling round of the LP scheduler is written
+    pub model_dump_dir: Option<PathBuf>,
+    /// The format of the dumped models (`lp` by default)
+    pub model_dump_format: Option<ModelFormat>,
+    /// Scheduler-specific parameters given by the `[scheduler.params]` table
+    #[serde(default)]
+    pub params: SchedulerParams,
+}
+
+/// A scheduling starts when `num_jobs` jobs are waiting or `max_wait` cycles have passed since
+/// the request of the oldest waiting job, whichever comes first. An unset condition is never
+/// met, and a scheduling also starts when no more events can complete the batch.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchingWindow {
+    pub num_jobs: Option<u32>,
+    pub max_wait: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,14 +92,36 @@ impl SimulationConfig {
     pub fn from_toml(path: PathBuf) -> Result<SimulationConfig> {
         let toml_str = std::fs::read_to_string(path)?;
         let config: SimulationConfig = toml::from_str(&toml_str)?;
+        config.scheduler.validate()?;
         Ok(config)
     }
 }
 
+impl SchedulerConfig {
+    /// Checks the values which cannot be rejected by the deserialization.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(adaptive) = &self.adaptive_batch {
+            adaptive.validate()?;
+        }
+        if let Some(shares) = &self.tenant_shares {
+            validate_tenant_shares(shares)?;
+        }
+        if let Some(predictor) = &self.latency_predictor {
+            predictor.validate()?;
+        }
+        if let Some(solver) = self.solver {
+            if !solver.is_available() {
+                return Err(QMPError::UnavailableSolver(solver).into());
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use crate::config::SimulationConfig;
-    use crate::scheduler::SchedulerKind;
+    use crate::scheduler::lp_scheduler::LPSolver;
     use crate::test_utils;
     use std::path::PathBuf;
 
@@ -56,8 +134,23 @@ pub mod test {
         assert!(config.micro_sec_per_cycle == 100);
         assert!(!config.enable_defrag);
         assert!(config.defrag_interval == Some(1000));
-        assert!(config.scheduler.kind == SchedulerKind::Greedy);
+        assert!(config.scheduler.kind == "greedy");
         assert!(config.scheduler.time_limit == Some(60));
         assert!(config.scheduler.batch_size == Some(3));
     }
+
+    #[test]
+    fn test_validate_solver() {
+        let path = PathBuf::from(test_utils::TEST_TOML_FILE);
+        let mut config = SimulationConfig::from_toml(path).unwrap();
+        for solver in [
+            LPSolver::Cbc,
+            LPSolver::Cplex,
+            LPSolver::Highs,
+            LPSolver::Minilp,
+        ] {
+            config.scheduler.solver = Some(solver);
+            assert_eq!(config.scheduler.validate().is_ok(), solver.is_available());
+        }
+    }
 }
@@ -3,10 +3,76 @@ use std::path::PathBuf;
 
 use crate::program::Program;
 
+/// A job request in a dataset.
+///
+/// A request is given either as a tuple `[t, program_id]` or as an object with the optional
+/// fields `priority`, `deadline` and `tenant`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "JobRequestFormat")]
+pub struct JobRequest {
+    pub time: u64,
+    pub program_id: usize,
+    /// Jobs with a larger priority are scheduled first. The default value is 0.
+    pub priority: i32,
+    /// The time (in cycles) by which the job should be completed.
+    pub deadline: Option<u64>,
+    /// The tenant (user group) who requests the job.
+    pub tenant: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JobRequestFormat {
+    Tuple(u64, usize),
+    Object {
+        time: u64,
+        program_id: usize,
+        #[serde(default)]
+        priority: i32,
+        #[serde(default)]
+        deadline: Option<u64>,
+        #[serde(default)]
+        tenant: Option<String>,
+    },
+}
+
+impl From<JobRequestFormat> for JobRequest {
+    fn from(item: JobRequestFormat) -> Self {
+        match item {
+            JobRequestFormat::Tuple(time, program_id) => JobRequest::new(time, program_id),
+            JobRequestFormat::Object {
+                time,
+                program_id,
+                priority,
+                deadline,
+                tenant,
+            } => JobRequest {
+                time,
+                program_id,
+                priority,
+                deadline,
+                tenant,
+            },
+        }
+    }
+}
+
+impl JobRequest {
+    pub fn new(time: u64, program_id: usize) -> Self {
+        Self {
+            time,
+            program_id,
+            priority: 0,
+            deadline: None,
+            tenant: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dataset {
     programs: Vec<Program>,
-    job_requests: Vec<(u64, usize)>,
+    job_requests: Vec<JobRequest>,
 }
 
 impl Dataset {
@@ -16,19 +82,50 @@ impl Dataset {
         Ok(dataset)
     }
 
-    pub fn get_request(&self, id: usize) -> (u64, &Program) {
-        let (time, program_id) = self.job_requests[id];
-        (time, &self.programs[program_id])
+    pub fn get_request(&self, id: usize) -> (&JobRequest, &Program) {
+        let request = &self.job_requests[id];
+        (request, &self.programs[request.program_id])
     }
 
     pub fn num_requests(&self) -> usize {
         self.job_requests.len()
     }
 
-    pub fn requests(&self) -> Vec<(u64, &Program)> {
+    pub fn requests(&self) -> Vec<(&JobRequest, &Program)> {
         self.job_requests
             .iter()
-            .map(|&(t, id)| (t, &self.programs[id]))
+            .map(|req| (req, &self.programs[req.program_id]))
             .collect()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::dataset::{Dataset, JobRequest};
+
+    #[test]
+    fn test_read_job_requests() {
+        let json = r#"{
+            "programs": [],
+            "job_requests": [
+                [3, 0],
+                {"time": 5, "program_id": 1, "priority": 2, "deadline": 100, "tenant": "a"},
+                {"time": 7, "program_id": 0}
+            ]
+        }"#;
+        let dataset: Dataset = serde_json::from_str(json).unwrap();
+        assert_eq!(dataset.num_requests(), 3);
+        assert_eq!(dataset.job_requests[0], JobRequest::new(3, 0));
+        assert_eq!(
+            dataset.job_requests[1],
+            JobRequest {
+                time: 5,
+                program_id: 1,
+                priority: 2,
+                deadline: Some(100),
+                tenant: Some("a".to_string()),
+            }
+        );
+        assert_eq!(dataset.job_requests[2], JobRequest::new(7, 0));
+    }
+}
@@ -1,7 +1,8 @@
 use crate::{
     config::SimulationConfig,
+    job::JobID,
     program::{
-        cut_program_at_z, is_overlap, Coordinate, Cuboid, Program, ProgramCounter, ProgramFormat,
+        cut_program_at_z, Coordinate, Cuboid, Program, ProgramCounter, ProgramFormat, SpatialIndex,
     },
 };
 use std::collections::{BTreeMap, BTreeSet};
@@ -11,8 +12,10 @@ pub struct Environment {
     config: SimulationConfig,
     /// All programs assigned by a scheduler
     issued_programs: Vec<Program>,
+    /// The job of each program in `issued_programs` (`None` if issued without a job)
+    issued_jobs: Vec<Option<JobID>>,
     /// All running programs
-    running_programs: Vec<Program>,
+    running_programs: SpatialIndex,
     /// The maximum z position of issued programs + 1.
     end_pc: u64,
     /// The global time in cycles. This is not equal to the program counter because the program counter may
@@ -22,9 +25,13 @@ pub struct Environment {
     /// TODO: add program counter to each program (or job)
     program_counter: u64,
     suspend_until: BTreeMap<u64, ProgramCounter>,
+    /// The number of cycles the execution has been suspended at each program counter.
+    waited_cycles: BTreeMap<ProgramCounter, u64>,
     /// for defrag
     next_defrag_cands: BTreeSet<ProgramCounter>,
     last_defrag_point: u64,
+    /// The number of defragmentations performed, which changes whenever programs are moved
+    defrag_count: u64,
     defrag_cost_sum: u64,
     defrag_move_areas: Vec<Cuboid>,
 }
@@ -34,13 +41,16 @@ impl Environment {
         Self {
             config,
             issued_programs: Vec::new(),
-            running_programs: Vec::new(),
+            issued_jobs: Vec::new(),
+            running_programs: SpatialIndex::new(),
             end_pc: 0,
             current_time: 0,
             program_counter: 0,
             suspend_until: BTreeMap::new(),
+            waited_cycles: BTreeMap::new(),
             next_defrag_cands: BTreeSet::new(),
             last_defrag_point: 0,
+            defrag_count: 0,
             defrag_cost_sum: 0,
             defrag_move_areas: Vec::new(),
         }
@@ -61,15 +71,25 @@ impl Environment {
     }
 
     pub fn can_issue(&self, p: &Program) -> bool {
-        let is_overlap = self.running_programs.iter().any(|p2| is_overlap(p, p2));
-        self.is_in_range(p) && !is_overlap
+        self.is_in_range(p) && !self.running_programs.is_overlap(p)
     }
 
     pub fn issue_program(&mut self, p: &Program) -> bool {
+        self.issue(p, None)
+    }
+
+    /// Issues the program of the job. The job is tracked through defragmentations (see
+    /// `job_end_pcs`).
+    pub fn issue_job(&mut self, job_id: JobID, p: &Program) -> bool {
+        self.issue(p, Some(job_id))
+    }
+
+    fn issue(&mut self, p: &Program, job_id: Option<JobID>) -> bool {
         let can_issue = self.can_issue(p);
         if can_issue {
             self.issued_programs.push(p.clone());
-            self.running_programs.push(p.clone());
+            self.issued_jobs.push(job_id);
+            self.running_programs.insert(p.clone());
             match p.format() {
                 ProgramFormat::Polycube(p) => {
                     for b in p.blocks() {
@@ -93,7 +113,20 @@ impl Environment {
         &self.issued_programs
     }
 
-    pub fn running_programs(&self) -> &Vec<Program> {
+    /// Returns the end point (the maximum z position + 1) of each issued job at its current
+    /// location, which may have been moved by defragmentations.
+    pub fn job_end_pcs(&self) -> BTreeMap<JobID, ProgramCounter> {
+        let mut end_pcs = BTreeMap::new();
+        for (p, job_id) in self.issued_programs.iter().zip(&self.issued_jobs) {
+            if let Some(job_id) = job_id {
+                let end_pc = end_pcs.entry(*job_id).or_insert(0);
+                *end_pc = ProgramCounter::max(*end_pc, p.z2() as ProgramCounter);
+            }
+        }
+        end_pcs
+    }
+
+    pub fn running_programs(&self) -> &SpatialIndex {
         &self.running_programs
     }
 
@@ -130,6 +163,26 @@ impl Environment {
         result + self.end_pc() - tmp_program_counter + self.defrag_cost_sum
     }
 
+    /// Returns the time when the global program counter reaches `pc`. For future program
+    /// counters, the result assumes that no more suspension will be added.
+    pub fn time_at_pc(&self, pc: ProgramCounter) -> u64 {
+        if pc <= self.program_counter {
+            let waited: u64 = self.waited_cycles.range(..pc).map(|(_, c)| c).sum();
+            return pc + waited;
+        }
+
+        let mut tmp_current_time = self.current_time;
+        let mut tmp_program_counter = self.program_counter;
+        for (suspend_point, until) in self.suspend_until.range(..pc) {
+            assert!(*suspend_point >= tmp_program_counter);
+            tmp_current_time += *suspend_point - tmp_program_counter;
+            tmp_program_counter = *suspend_point;
+            tmp_current_time = tmp_current_time.max(*until);
+        }
+
+        tmp_current_time + pc - tmp_program_counter
+    }
+
     /// Returns the global program counter
     pub fn global_pc(&self) -> u64 {
         self.program_counter
@@ -160,14 +213,18 @@ impl Environment {
                 } else {
                     0
                 };
-                if advance_cycles >= need_to_wait {
+                let waited = if advance_cycles >= need_to_wait {
                     self.current_time += need_to_wait;
                     advance_cycles -= need_to_wait;
+                    need_to_wait
                 } else {
+                    let waited = advance_cycles;
                     self.current_time += advance_cycles;
                     advance_cycles = 0;
                     self.suspend_until.insert(suspend_point, until);
-                }
+                    waited
+                };
+                *self.waited_cycles.entry(suspend_point).or_insert(0) += waited;
             } else {
                 // leave the entry
                 self.suspend_until.insert(suspend_point, until);
@@ -179,7 +236,7 @@ impl Environment {
         self.program_counter += advance_cycles;
 
         self.running_programs
-            .retain(|program| program.z2() > self.program_counter as i32);
+            .remove_finished(self.program_counter as i32);
     }
 
     pub fn defrag(&mut self) {
@@ -205,40 +262,40 @@ impl Environment {
         assert!(self.program_counter <= defrag_point);
 
         // TODO: more efficient implementation?
-        let (below, above) = self.issued_programs.iter().fold(
-            (Vec::new(), Vec::new()),
-            |(mut below, mut above), program| {
-                let (below_c, above_c) = cut_program_at_z(program.clone(), defrag_point as i32);
-                if let Some(below_c) = below_c {
-                    below.push(below_c);
-                }
-                if let Some(above_c) = above_c {
-                    above.push(above_c);
-                }
-                (below, above)
-            },
-        );
+        let mut below = Vec::new();
+        let mut above = Vec::new();
+        let mut below_jobs = Vec::new();
+        let mut above_jobs = Vec::new();
+        for (program, job_id) in self.issued_programs.iter().zip(&self.issued_jobs) {
+            let (below_c, above_c) = cut_program_at_z(program.clone(), defrag_point as i32);
+            if let Some(below_c) = below_c {
+                below.push(below_c);
+                below_jobs.push(*job_id);
+            }
+            if let Some(above_c) = above_c {
+                above.push(above_c);
+                above_jobs.push(*job_id);
+            }
+        }
         //tracing::debug!("\n  defrag at {},\n  below: {:?}\n  above: {:?}", defrag_point, below, above);
         self.issued_programs.clear();
         self.issued_programs.extend(below);
         let (cost, move_areas, above) = drop_programs(defrag_point, above);
         self.issued_programs.extend(above);
+        self.issued_jobs = below_jobs;
+        self.issued_jobs.extend(above_jobs);
         self.defrag_move_areas.extend(move_areas);
 
         self.running_programs = self
             .issued_programs
             .iter()
-            .filter_map(|p| {
-                if p.z2() as ProgramCounter > self.program_counter {
-                    Some(p.clone())
-                } else {
-                    None
-                }
-            })
+            .filter(|p| p.z2() as ProgramCounter > self.program_counter)
+            .cloned()
             .collect();
 
         assert!(self.last_defrag_point <= defrag_point);
         self.last_defrag_point = defrag_point;
+        self.defrag_count += 1;
         self.defrag_cost_sum += cost;
         tracing::debug!("Defragmentation at {} with cost {}", defrag_point, cost);
     }
@@ -247,22 +304,24 @@ impl Environment {
         &self.defrag_move_areas
     }
 
+    pub fn defrag_count(&self) -> u64 {
+        self.defrag_count
+    }
+
     pub fn defrag_cost_sum(&self) -> u64 {
         self.defrag_cost_sum
     }
 
     pub fn validate(&self) {
-        for i in 0..self.issued_programs.len() {
-            assert!(self.is_in_range(&self.issued_programs[i]));
-            for j in i + 1..self.issued_programs.len() {
-                let p1 = &self.issued_programs[i];
-                let p2 = &self.issued_programs[j];
-                if is_overlap(p1, p2) {
-                    tracing::debug!("{:?}", p1);
-                    tracing::debug!("{:?}", p2);
-                    panic!();
-                }
+        let mut index = SpatialIndex::new();
+        for p1 in &self.issued_programs {
+            assert!(self.is_in_range(p1));
+            if let Some(p2) = index.overlapping(p1).next() {
+                tracing::debug!("{:?}", p1);
+                tracing::debug!("{:?}", p2);
+                panic!();
             }
+            index.insert(p1.clone());
         }
     }
 }
@@ -273,24 +332,26 @@ fn drop_programs(
     defrag_point: ProgramCounter,
     programs: Vec<Program>,
 ) -> (u64, Vec<Cuboid>, Vec<Program>) {
+    // Cuboids are numbered to return them in the given order.
     let mut cuboids: Vec<_> = programs
         .into_iter()
-        .map(|p| {
+        .enumerate()
+        .map(|(i, p)| {
             assert!(p.cuboid().map_or(false, |c| c.len() == 1));
-            p.cuboid().unwrap()[0].clone()
+            (i, p.cuboid().unwrap()[0].clone())
         })
         .collect();
 
     let mut move_areas = Vec::new();
 
     // drop by y position
-    cuboids.sort_by_key(|c| c.y1());
-    let mut cs_drop_x: Vec<Cuboid> = Vec::new();
+    cuboids.sort_by_key(|(_, c)| c.y1());
+    let mut cs_drop_x: Vec<(usize, Cuboid)> = Vec::new();
     let mut y_cost_table = Vec::new();
-    for mut c in cuboids {
+    for (i, mut c) in cuboids {
         assert!(c.z1() as ProgramCounter >= defrag_point);
         let mut new_y1 = 0;
-        for other in &cs_drop_x {
+        for (_, other) in &cs_drop_x {
             // collision check
             let is_overlap_x = !(c.x2() <= other.x1() || other.x2() <= c.x1());
             let is_overlap_z = !(c.z2() <= other.z1() || other.z2() <= c.z1());
@@ -320,17 +381,17 @@ fn drop_programs(
                 }
             }
         }
-        cs_drop_x.push(c);
+        cs_drop_x.push((i, c));
     }
     let y_cost = y_cost_table.into_iter().max().unwrap_or(0);
 
     // drop by x position
-    cs_drop_x.sort_by_key(|c| c.x1());
-    let mut result: Vec<Cuboid> = Vec::new();
+    cs_drop_x.sort_by_key(|(_, c)| c.x1());
+    let mut result: Vec<(usize, Cuboid)> = Vec::new();
     let mut x_cost_table = Vec::new();
-    for mut c in cs_drop_x {
+    for (i, mut c) in cs_drop_x {
         let mut new_x1 = 0;
-        for other in &result {
+        for (_, other) in &result {
             // collision check
             let is_overlap_y = !(c.y2() <= other.y1() || other.y2() <= c.y1());
             let is_overlap_z = !(c.z2() <= other.z1() || other.z2() <= c.z1());
@@ -360,13 +421,14 @@ fn drop_programs(
                 }
             }
         }
-        result.push(c.clone());
+        result.push((i, c));
     }
     let x_cost = x_cost_table.into_iter().max().unwrap_or(0);
 
+    result.sort_by_key(|(i, _)| *i);
     let result = result
         .into_iter()
-        .map(|c| Program::new(ProgramFormat::Cuboid(vec![c])))
+        .map(|(_, c)| Program::new(ProgramFormat::Cuboid(vec![c])))
         .collect();
     (x_cost + y_cost, move_areas, result)
 }
@@ -403,6 +465,54 @@ mod test {
         assert_eq!(env.issued_programs(), &expected);
     }
 
+    #[test]
+    fn test_time_at_pc() {
+        let config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let mut env = Environment::new(config);
+
+        env.suspend_at(2, 5);
+        env.suspend_at(8, 20);
+        assert_eq!(env.time_at_pc(2), 2);
+        assert_eq!(env.time_at_pc(3), 6);
+        assert_eq!(env.time_at_pc(9), 21);
+
+        env.advance_by(4);
+        assert_eq!(env.global_pc(), 2);
+        assert_eq!(env.current_time(), 4);
+        assert_eq!(env.time_at_pc(3), 6);
+
+        env.advance_by(6);
+        assert_eq!(env.global_pc(), 7);
+        assert_eq!(env.time_at_pc(1), 1);
+        assert_eq!(env.time_at_pc(3), 6);
+        assert_eq!(env.time_at_pc(9), 21);
+    }
+
+    #[test]
+    fn test_job_end_pcs_after_defrag() {
+        let config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let mut env = Environment::new(config);
+        let cuboid = |x, y, z, size_z| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(x, y, z),
+                2,
+                2,
+                size_z,
+            )]))
+        };
+        assert!(env.issue_job(0, &cuboid(0, 2, 0, 4)));
+        assert!(env.issue_job(1, &cuboid(2, 3, 3, 2)));
+        assert!(env.issue_program(&cuboid(4, 0, 0, 1)));
+
+        // The first job is cut at the defragmentation point, and the upper part of it and the
+        // second job are moved.
+        env.defrag_at(2);
+        assert_eq!(env.issued_programs().len(), 4);
+        assert!(env.issued_programs().contains(&cuboid(2, 0, 3, 2)));
+        let end_pcs: Vec<_> = env.job_end_pcs().into_iter().collect();
+        assert_eq!(end_pcs, vec![(0, 4), (1, 5)]);
+    }
+
     #[test]
     fn test_drop_programs() {
         let c1 = Cuboid::new(Coordinate::new(0, 1, 0), 2, 2, 2);
@@ -426,4 +536,20 @@ mod test {
         assert!(ps.contains(&p2_moved));
         assert!(ps.contains(&p3_moved));
     }
+
+    #[test]
+    fn test_drop_programs_keeps_order() {
+        let cuboid = |x, y, z| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(x, y, z),
+                2,
+                2,
+                2,
+            )]))
+        };
+        // The programs are not given in the order of their y (or x) positions.
+        let programs = vec![cuboid(1, 3, 1), cuboid(2, 0, 0), cuboid(0, 1, 0)];
+        let (_, _, ps) = drop_programs(0, programs);
+        assert_eq!(ps, vec![cuboid(0, 2, 1), cuboid(2, 0, 0), cuboid(0, 0, 0)]);
+    }
 }
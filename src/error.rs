@@ -1,6 +1,8 @@
+use good_lp::Variable;
 use thiserror::Error;
 
 use crate::job::{Job, JobID};
+use crate::scheduler::lp_scheduler::LPSolver;
 use crate::scheduler::Schedule;
 
 #[derive(Error, Debug)]
@@ -11,6 +13,20 @@ pub enum QMPError {
     InvalidSchedule { job: Job, schedule: Schedule },
     #[error("Violate timing constraint")]
     ViolateTimingConstraint,
+    #[error("Unknown scheduler `{name}` (available: {available})")]
+    UnknownScheduler { name: String, available: String },
+    #[error("Scheduler `{0}` is already registered")]
+    DuplicateScheduler(String),
+    #[error("Variable {0:?} is not in the dumped model")]
+    UnknownModelVariable(Variable),
+    #[error("Invalid batch size bounds (min_batch_size = {min}, max_batch_size = {max})")]
+    InvalidBatchSizeBounds { min: u32, max: u32 },
+    #[error("The solver backend {0:?} is not enabled in this build")]
+    UnavailableSolver(LPSolver),
+    #[error("The share of tenant `{tenant}` must be positive (share = {share})")]
+    InvalidTenantShare { tenant: String, share: f64 },
+    #[error("The latency predictor parameter `{name}` is out of range ({name} = {value})")]
+    InvalidLatencyPredictorParam { name: &'static str, value: f64 },
 }
 
 impl QMPError {
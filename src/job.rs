@@ -21,6 +21,12 @@ pub struct Job {
     pub id: JobID,
     pub requested_time: u64,
     pub program: Program,
+    /// Jobs with a larger priority are scheduled first.
+    pub priority: i32,
+    /// The time (in cycles) by which the job should be completed.
+    pub deadline: Option<u64>,
+    /// The tenant (user group) who requested the job.
+    pub tenant: Option<String>,
     /// The time when the execution of this job will start.
     start_time: Option<u64>,
     status: JobStatus,
@@ -32,11 +38,29 @@ impl Job {
             id,
             requested_time,
             program,
+            priority: 0,
+            deadline: None,
+            tenant: None,
             start_time: None,
             status: JobStatus::Waiting,
         }
     }
 
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Option<u64>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn with_tenant(mut self, tenant: Option<String>) -> Self {
+        self.tenant = tenant;
+        self
+    }
+
     pub fn start_time(&self) -> Option<u64> {
         self.start_time
     }
@@ -71,9 +95,15 @@ impl Job {
     }
 }
 
+/// A job is greater than another one if it should be scheduled earlier, i.e., it has a larger
+/// priority, an earlier deadline or an earlier requested time (in this order).
 impl Ord for Job {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.requested_time.cmp(&self.requested_time)
+        let deadline = |job: &Job| job.deadline.unwrap_or(u64::MAX);
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| deadline(other).cmp(&deadline(self)))
+            .then_with(|| other.requested_time.cmp(&self.requested_time))
     }
 }
 
@@ -82,3 +112,27 @@ impl PartialOrd for Job {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::job::Job;
+    use crate::program::{Coordinate, Cuboid, Program, ProgramFormat};
+
+    #[test]
+    fn test_job_order() {
+        let program = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(0, 0, 0),
+            1,
+            1,
+            1,
+        )]));
+        let j1 = Job::new(0, 0, program.clone());
+        let j2 = Job::new(1, 5, program.clone()).with_deadline(Some(10));
+        let j3 = Job::new(2, 10, program.clone()).with_priority(1);
+        let j4 = Job::new(3, 3, program);
+
+        let mut jobs = [j4.clone(), j1.clone(), j3.clone(), j2.clone()];
+        jobs.sort_by(|a, b| b.cmp(a));
+        assert_eq!(jobs, [j3, j2, j1, j4]);
+    }
+}
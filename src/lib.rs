@@ -1,3 +1,4 @@
+pub mod bounds;
 pub mod config;
 pub mod dataset;
 pub mod environment;
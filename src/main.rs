@@ -5,9 +5,7 @@ use anyhow::Result;
 
 use qmp_scheduler::config::SimulationConfig;
 use qmp_scheduler::dataset::Dataset;
-use qmp_scheduler::scheduler::{
-    CornerGreedyScheduler, GreedyScheduler, LPScheduler, Scheduler, SchedulerKind,
-};
+use qmp_scheduler::scheduler::SchedulerRegistry;
 use qmp_scheduler::simulation::Simulator;
 
 #[derive(Parser, Debug)]
@@ -37,11 +35,7 @@ fn main() -> Result<()> {
     let dataset = Dataset::from_json_file(args.dataset_file)?;
 
     tracing::info!("Configure the scheduler: {:?}", config.scheduler.kind);
-    let scheduler: Box<dyn Scheduler> = match config.scheduler.kind {
-        SchedulerKind::Greedy => Box::new(GreedyScheduler::new(config.clone())),
-        SchedulerKind::CornerGreedy => Box::new(CornerGreedyScheduler::new(config.clone())),
-        SchedulerKind::LP => Box::new(LPScheduler::new(config.clone())),
-    };
+    let scheduler = SchedulerRegistry::default().create(&config)?;
 
     let simulator = Simulator::new(config, dataset, scheduler);
 
@@ -26,7 +26,12 @@ impl Preprocessor for ConvertToCuboid {
                 let cuboids = poly_bins
                     .into_iter()
                     .filter(|poly| poly.size() > 0)
-                    .map(|poly| Cuboid::from(&poly))
+                    .map(|poly| {
+                        // keep the relative position of each part in the program
+                        let cuboid = Cuboid::from(&poly);
+                        let pos = poly.pos() - p.pos();
+                        Cuboid::new(pos, cuboid.size_x(), cuboid.size_y(), cuboid.size_z())
+                    })
                     .collect();
                 Program::new(ProgramFormat::Cuboid(cuboids))
             }
@@ -52,5 +57,7 @@ mod test {
         let cuboids = converter.process(poly).cuboid().unwrap().clone();
         assert!(cuboids.len() == num_cuboids as usize);
         assert!(cuboids.iter().all(|c| c.size_z() == 3 || c.size_z() == 4));
+        assert_eq!(cuboids[0].z1(), 0);
+        assert!(cuboids.windows(2).all(|cs| cs[0].z2() == cs[1].z1()));
     }
 }
@@ -1,8 +1,12 @@
 pub mod cuboid;
 pub mod polycube;
+pub mod spatial_index;
+pub mod voxels;
 
 pub use cuboid::Cuboid;
 pub use polycube::{Coordinate, Polycube};
+pub use spatial_index::SpatialIndex;
+pub use voxels::Voxels;
 
 use serde::{Deserialize, Serialize};
 
@@ -51,13 +55,11 @@ impl Program {
         &self.format
     }
 
+    /// Returns the minimum corner of the program.
     pub fn pos(&self) -> Coordinate {
         match self.format() {
             ProgramFormat::Polycube(p) => p.pos(),
-            ProgramFormat::Cuboid(cs) => {
-                assert!(cs.len() == 1);
-                cs[0].pos().clone()
-            }
+            ProgramFormat::Cuboid(_) => self.bounding_box().pos().clone(),
         }
     }
 
@@ -68,6 +70,67 @@ impl Program {
         }
     }
 
+    /// Returns the smallest cuboid containing the whole program.
+    pub fn bounding_box(&self) -> Cuboid {
+        let (x1, x2, y1, y2, z1, z2) = match self.format() {
+            ProgramFormat::Polycube(p) => (
+                p.min_x(),
+                p.max_x() + 1,
+                p.min_y(),
+                p.max_y() + 1,
+                p.min_z(),
+                p.max_z() + 1,
+            ),
+            ProgramFormat::Cuboid(cs) => cs.iter().fold(
+                (i32::MAX, i32::MIN, i32::MAX, i32::MIN, i32::MAX, i32::MIN),
+                |(x1, x2, y1, y2, z1, z2), c| {
+                    (
+                        i32::min(x1, c.x1()),
+                        i32::max(x2, c.x2()),
+                        i32::min(y1, c.y1()),
+                        i32::max(y2, c.y2()),
+                        i32::min(z1, c.z1()),
+                        i32::max(z2, c.z2()),
+                    )
+                },
+            ),
+        };
+        Cuboid::new(
+            Coordinate::new(x1, y1, z1),
+            (x2 - x1) as usize,
+            (y2 - y1) as usize,
+            (z2 - z1) as usize,
+        )
+    }
+
+    /// Returns all blocks occupied by the program.
+    pub fn blocks(&self) -> Vec<Coordinate> {
+        match self.format() {
+            ProgramFormat::Polycube(p) => p.blocks().clone(),
+            ProgramFormat::Cuboid(cs) => cs
+                .iter()
+                .flat_map(|c| {
+                    (c.x1()..c.x2()).flat_map(move |x| {
+                        (c.y1()..c.y2()).flat_map(move |y| {
+                            (c.z1()..c.z2()).map(move |z| Coordinate::new(x, y, z))
+                        })
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the spacetime volume, i.e., the number of blocks occupied by the program.
+    pub fn volume(&self) -> u64 {
+        match &self.format {
+            ProgramFormat::Polycube(poly) => poly.blocks().len() as u64,
+            ProgramFormat::Cuboid(cs) => cs
+                .iter()
+                .map(|c| (c.size_x() * c.size_y() * c.size_z()) as u64)
+                .sum(),
+        }
+    }
+
     /// Returns the burst time (= execution time) in cycles
     pub fn burst_time(&self) -> u64 {
         match &self.format {
@@ -90,9 +153,7 @@ impl Program {
 }
 
 pub fn is_overlap_polycubes(p1: &Polycube, p2: &Polycube) -> bool {
-    p1.blocks()
-        .iter()
-        .any(|b1| p2.blocks().iter().any(|b2| b1 == b2))
+    polycube::is_collide(p1, p2)
 }
 
 pub fn is_overlap_cuboids(c1: &Cuboid, c2: &Cuboid) -> bool {
@@ -108,9 +169,9 @@ pub fn is_overlap_polycube_cuboid(p: &Polycube, c: &Cuboid) -> bool {
         cp.x <= b.x
             && b.x < cp.x + c.size_x() as i32
             && cp.y <= b.y
-            && b.y <= cp.y + c.size_y() as i32
+            && b.y < cp.y + c.size_y() as i32
             && cp.z <= b.z
-            && b.z <= cp.z + c.size_z() as i32
+            && b.z < cp.z + c.size_z() as i32
     })
 }
 
@@ -178,7 +239,10 @@ pub fn cut_program_at_z(p: Program, z: i32) -> (Option<Program>, Option<Program>
 
 #[cfg(test)]
 pub mod test {
-    use crate::program::{cut_program_at_z, Coordinate, Cuboid, Program, ProgramFormat};
+    use crate::program::{
+        cut_program_at_z, is_overlap_polycube_cuboid, Coordinate, Cuboid, Polycube, Program,
+        ProgramFormat,
+    };
 
     #[test]
     fn test_cut_program_at_z() {
@@ -194,4 +258,23 @@ pub mod test {
         assert_eq!(below_cs, vec![c1, c2_below]);
         assert_eq!(above_cs, vec![c2_above, c3]);
     }
+
+    #[test]
+    fn test_is_overlap_polycube_cuboid() {
+        let c = Cuboid::new(Coordinate::new(1, 1, 1), 2, 2, 2);
+        let inside = Polycube::new(vec![Coordinate::new(2, 2, 2)]);
+        assert!(is_overlap_polycube_cuboid(&inside, &c));
+        // Blocks just beyond the cuboid in each direction do not overlap with it.
+        for (x, y, z) in [
+            (3, 1, 1),
+            (1, 3, 1),
+            (1, 1, 3),
+            (0, 1, 1),
+            (1, 0, 1),
+            (1, 1, 0),
+        ] {
+            let outside = Polycube::new(vec![Coordinate::new(x, y, z)]);
+            assert!(!is_overlap_polycube_cuboid(&outside, &c));
+        }
+    }
 }
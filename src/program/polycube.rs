@@ -5,6 +5,8 @@ use rand::Rng;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_tuple::{Deserialize_tuple, Serialize_tuple};
 
+use crate::program::Voxels;
+
 #[derive(
     Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize_tuple, Serialize_tuple,
 )]
@@ -36,6 +38,18 @@ impl std::ops::Add for Coordinate {
     }
 }
 
+impl std::ops::Sub for Coordinate {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        }
+    }
+}
+
 impl Coordinate {
     pub fn new(x: i32, y: i32, z: i32) -> Self {
         Self { x, y, z }
@@ -57,6 +71,9 @@ pub struct Polycube {
     min_z: i32,
     #[serde(skip)]
     max_z: i32,
+    /// The occupancy of `blocks`
+    #[serde(skip)]
+    voxels: Voxels,
 }
 
 /// Returns (min_x, max_x, min_y, max_y, min_z, max_z)
@@ -97,26 +114,18 @@ impl<'de> Deserialize<'de> for Polycube {
         }
 
         let temp = TempPolycube::deserialize(deserializer)?;
-        let (min_x, max_x, min_y, max_y, min_z, max_z) = calc_min_max_pos(&temp.blocks);
-
-        Ok(Polycube {
-            blocks: temp.blocks,
-            min_x,
-            max_x,
-            min_y,
-            max_y,
-            min_z,
-            max_z,
-        })
+        Ok(Polycube::new(temp.blocks))
     }
 }
 
 impl Polycube {
     pub fn new(blocks: Vec<Coordinate>) -> Self {
         let (min_x, max_x, min_y, max_y, min_z, max_z) = calc_min_max_pos(&blocks);
+        let voxels = blocks.iter().collect();
 
         Self {
             blocks,
+            voxels,
             min_x,
             max_x,
             min_y,
@@ -130,6 +139,10 @@ impl Polycube {
         &self.blocks
     }
 
+    pub fn voxels(&self) -> &Voxels {
+        &self.voxels
+    }
+
     pub fn min_x(&self) -> i32 {
         self.min_x
     }
@@ -165,6 +178,7 @@ impl Polycube {
         self.max_x = i32::max(self.max_x, coord.x);
         self.max_y = i32::max(self.max_y, coord.y);
         self.max_z = i32::max(self.max_z, coord.z);
+        self.voxels.insert(&coord);
         self.blocks.push(coord);
     }
 
@@ -184,8 +198,15 @@ impl Polycube {
     }
 }
 
-pub fn is_collide(_p1: &Polycube, _p2: &Polycube) -> bool {
-    unimplemented!()
+/// Returns true if the two polycubes share a block.
+pub fn is_collide(p1: &Polycube, p2: &Polycube) -> bool {
+    let is_apart = p1.max_x < p2.min_x
+        || p2.max_x < p1.min_x
+        || p1.max_y < p2.min_y
+        || p2.max_y < p1.min_y
+        || p1.max_z < p2.min_z
+        || p2.max_z < p1.min_z;
+    !is_apart && p1.voxels.is_collide(&p2.voxels)
 }
 
 #[allow(dead_code)]
@@ -239,6 +260,7 @@ pub fn create_random_polycube(num_block: i32) -> Polycube {
 
 #[cfg(test)]
 mod test {
+    use crate::program::polycube::is_collide;
     use crate::program::{Coordinate, Polycube};
 
     #[test]
@@ -249,4 +271,16 @@ mod test {
             &vec![Coordinate::new(0, 0, 0), Coordinate::new(0, 1, 0)]
         );
     }
+
+    #[test]
+    fn test_is_collide() {
+        let p1 = Polycube::from(&[(0, 0, 0), (1, 0, 0), (1, 1, 0), (1, 1, 1)]);
+        let p2 = Polycube::from(&[(0, 1, 0), (0, 1, 1), (0, 0, 1)]);
+        assert!(!is_collide(&p1, &p2));
+
+        let mut p3 = Polycube::from(&[(2, 1, 1), (2, 1, 2)]);
+        assert!(!is_collide(&p1, &p3));
+        p3.add_block(Coordinate::new(1, 1, 1));
+        assert!(is_collide(&p1, &p3) && is_collide(&p3, &p1));
+    }
 }
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use crate::program::{is_overlap, is_overlap_cuboids, Cuboid, Program};
+
+/// The length of the z range covered by a bucket.
+const BUCKET_SIZE: i32 = 8;
+
+/// A set of programs indexed by the z ranges of their bounding boxes.
+///
+/// Each program is registered in the buckets of fixed-length z ranges intersecting its bounding
+/// box. The overlap check of a program only compares it with the programs in the same buckets
+/// whose bounding boxes intersect its bounding box, so it does not depend on the number of
+/// programs far from it in z (i.e., finished or future programs). Programs are also keyed by the
+/// end of their bounding boxes, so finished programs are dropped without visiting the others.
+#[derive(Debug, Clone, Default)]
+pub struct SpatialIndex {
+    /// id -> (bounding box, program). Ids increase in the order of insertion.
+    entries: BTreeMap<usize, (Cuboid, Program)>,
+    /// bucket -> ids of the programs intersecting the bucket
+    buckets: BTreeMap<i32, Vec<usize>>,
+    /// z2 of the bounding box -> ids of the programs
+    ends: BTreeMap<i32, Vec<usize>>,
+    next_id: usize,
+}
+
+fn bucket_range(bbox: &Cuboid) -> RangeInclusive<i32> {
+    bbox.z1().div_euclid(BUCKET_SIZE)..=(bbox.z2() - 1).div_euclid(BUCKET_SIZE)
+}
+
+/// Removes `id` from the list at `key` and the list itself if it gets empty.
+fn unregister(map: &mut BTreeMap<i32, Vec<usize>>, key: i32, id: usize) {
+    let ids = map.get_mut(&key).unwrap();
+    ids.retain(|&i| i != id);
+    if ids.is_empty() {
+        map.remove(&key);
+    }
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the program and returns its id.
+    pub fn insert(&mut self, program: Program) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let bbox = program.bounding_box();
+        for bucket in bucket_range(&bbox) {
+            self.buckets.entry(bucket).or_default().push(id);
+        }
+        self.ends.entry(bbox.z2()).or_default().push(id);
+        self.entries.insert(id, (bbox, program));
+        id
+    }
+
+    /// Removes the program with the given id and returns it.
+    pub fn remove(&mut self, id: usize) -> Option<Program> {
+        let (bbox, program) = self.entries.remove(&id)?;
+        for bucket in bucket_range(&bbox) {
+            unregister(&mut self.buckets, bucket, id);
+        }
+        unregister(&mut self.ends, bbox.z2(), id);
+        Some(program)
+    }
+
+    /// Removes the programs whose bounding boxes end at or below `z` (i.e., the programs finished
+    /// when the program counter is `z`). The cost is proportional to the number of removed
+    /// programs.
+    pub fn remove_finished(&mut self, z: i32) {
+        while let Some(entry) = self.ends.first_entry() {
+            if *entry.key() > z {
+                break;
+            }
+            for id in entry.remove() {
+                let (bbox, _) = self.entries.remove(&id).unwrap();
+                for bucket in bucket_range(&bbox) {
+                    unregister(&mut self.buckets, bucket, id);
+                }
+            }
+        }
+    }
+
+    /// Removes the programs for which `f` returns false.
+    pub fn retain<F: FnMut(&Program) -> bool>(&mut self, mut f: F) {
+        let removed: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, (_, p))| !f(p))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in removed {
+            self.remove(id);
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Program> {
+        self.entries.get(&id).map(|(_, p)| p)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the programs in the order of insertion.
+    pub fn iter(&self) -> impl Iterator<Item = &Program> {
+        self.entries.values().map(|(_, p)| p)
+    }
+
+    /// Returns the ids of the programs whose bounding boxes intersect `bbox`.
+    fn candidates<'a>(&'a self, bbox: &'a Cuboid) -> impl Iterator<Item = usize> + 'a {
+        let range = bucket_range(bbox);
+        let first = *range.start();
+        self.buckets.range(range).flat_map(move |(&bucket, ids)| {
+            ids.iter().copied().filter(move |id| {
+                let (other, _) = &self.entries[id];
+                // Each program is reported only in the first bucket shared with `bbox`.
+                let other_first = *bucket_range(other).start();
+                bucket == first.max(other_first) && is_overlap_cuboids(bbox, other)
+            })
+        })
+    }
+
+    /// Returns the programs overlapping with the given one.
+    pub fn overlapping<'a>(&'a self, program: &'a Program) -> impl Iterator<Item = &'a Program> {
+        let bbox = program.bounding_box();
+        let ids: Vec<_> = self.candidates(&bbox).collect();
+        ids.into_iter()
+            .map(|id| self.get(id).unwrap())
+            .filter(move |p| is_overlap(program, p))
+    }
+
+    /// Returns true if the given program overlaps with any program in the index.
+    pub fn is_overlap(&self, program: &Program) -> bool {
+        self.overlapping(program).next().is_some()
+    }
+}
+
+impl FromIterator<Program> for SpatialIndex {
+    fn from_iter<I: IntoIterator<Item = Program>>(iter: I) -> Self {
+        let mut index = Self::new();
+        for program in iter {
+            index.insert(program);
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SpatialIndex;
+    use crate::program::{is_overlap, Coordinate, Cuboid, Polycube, Program, ProgramFormat};
+
+    fn cuboid(x: i32, y: i32, z: i32, sx: usize, sy: usize, sz: usize) -> Program {
+        Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(x, y, z),
+            sx,
+            sy,
+            sz,
+        )]))
+    }
+
+    #[test]
+    fn test_spatial_index() {
+        let programs = [
+            cuboid(0, 0, 0, 2, 2, 20),
+            cuboid(2, 0, 5, 2, 2, 3),
+            cuboid(0, 0, 20, 4, 4, 1),
+            Program::new(ProgramFormat::Polycube(Polycube::new(vec![
+                Coordinate::new(3, 3, 7),
+                Coordinate::new(3, 3, 8),
+            ]))),
+        ];
+        let mut index: SpatialIndex = programs.iter().cloned().collect();
+        assert_eq!(index.len(), 4);
+
+        let queries = [
+            cuboid(0, 0, 16, 4, 4, 5),
+            cuboid(1, 1, 6, 2, 2, 1),
+            cuboid(3, 3, 0, 1, 1, 30),
+            cuboid(2, 2, 0, 1, 1, 20),
+            cuboid(0, 0, -9, 1, 1, 9),
+        ];
+        for query in &queries {
+            let expected: Vec<_> = programs.iter().filter(|p| is_overlap(query, p)).collect();
+            let actual: Vec<_> = index.overlapping(query).collect();
+            assert_eq!(actual.len(), expected.len());
+            assert!(actual.iter().all(|p| expected.contains(p)));
+        }
+
+        // The long program is removed, so only the one at z = 20 overlaps.
+        assert_eq!(index.remove(0), Some(programs[0].clone()));
+        assert_eq!(index.remove(0), None);
+        let actual: Vec<_> = index.overlapping(&queries[0]).collect();
+        assert_eq!(actual, vec![&programs[2]]);
+
+        index.retain(|p| p.z2() > 10);
+        assert_eq!(index.iter().collect::<Vec<_>>(), vec![&programs[2]]);
+        assert!(!index.is_overlap(&queries[1]));
+
+        // Ids are not reused, and removed programs leave nothing behind.
+        let id = index.insert(programs[0].clone());
+        assert_eq!(id, 4);
+        assert_eq!(index.get(0), None);
+        index.remove(id);
+        index.retain(|_| false);
+        assert!(index.is_empty());
+        assert!(index.buckets.is_empty() && index.ends.is_empty());
+    }
+
+    #[test]
+    fn test_spatial_index_remove_finished() {
+        let mut index: SpatialIndex = (0..100).map(|z| cuboid(z % 4, 0, z, 1, 1, 3)).collect();
+        let long = index.insert(cuboid(0, 1, 0, 1, 1, 1000));
+
+        index.remove_finished(50);
+        assert_eq!(index.len(), 101 - 48);
+        assert!(index.iter().all(|p| p.z2() > 50));
+        assert!(index.get(long).is_some());
+        // The buckets only hold the remaining programs.
+        let registered: usize = index.buckets.values().map(|ids| ids.len()).sum();
+        let expected: usize = index
+            .iter()
+            .map(|p| super::bucket_range(&p.bounding_box()).count())
+            .sum();
+        assert_eq!(registered, expected);
+
+        index.remove_finished(1000);
+        assert!(index.is_empty());
+        assert!(index.buckets.is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use crate::program::Coordinate;
+
+/// The length of each side of a chunk. A chunk of 4x4x4 voxels is stored in a `u64`.
+const CHUNK_SIZE: i32 = 4;
+
+/// A set of voxels stored as a hashed bitset.
+///
+/// The space is divided into chunks of 4x4x4 voxels, and each non-empty chunk is stored as a
+/// 64-bit mask keyed by its position, so collision, union and difference are computed 64 voxels
+/// at a time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Voxels {
+    chunks: HashMap<Coordinate, u64>,
+    len: usize,
+}
+
+/// Returns the chunk containing the voxel and the bit of the voxel in the chunk.
+fn locate(pos: &Coordinate) -> (Coordinate, u64) {
+    let chunk = Coordinate::new(
+        pos.x.div_euclid(CHUNK_SIZE),
+        pos.y.div_euclid(CHUNK_SIZE),
+        pos.z.div_euclid(CHUNK_SIZE),
+    );
+    let bit = pos.x.rem_euclid(CHUNK_SIZE)
+        + pos.y.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE
+        + pos.z.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE * CHUNK_SIZE;
+    (chunk, 1 << bit)
+}
+
+impl Voxels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the voxel and returns true if it was not in the set.
+    pub fn insert(&mut self, pos: &Coordinate) -> bool {
+        let (chunk, bit) = locate(pos);
+        let mask = self.chunks.entry(chunk).or_default();
+        let is_new = *mask & bit == 0;
+        *mask |= bit;
+        if is_new {
+            self.len += 1;
+        }
+        is_new
+    }
+
+    /// Removes the voxel and returns true if it was in the set.
+    pub fn remove(&mut self, pos: &Coordinate) -> bool {
+        let (chunk, bit) = locate(pos);
+        let Some(mask) = self.chunks.get_mut(&chunk) else {
+            return false;
+        };
+        let is_present = *mask & bit != 0;
+        *mask &= !bit;
+        if *mask == 0 {
+            self.chunks.remove(&chunk);
+        }
+        if is_present {
+            self.len -= 1;
+        }
+        is_present
+    }
+
+    pub fn contains(&self, pos: &Coordinate) -> bool {
+        let (chunk, bit) = locate(pos);
+        self.chunks.get(&chunk).is_some_and(|mask| mask & bit != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the two sets share a voxel.
+    pub fn is_collide(&self, other: &Voxels) -> bool {
+        let (small, large) = if self.chunks.len() <= other.chunks.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        small
+            .chunks
+            .iter()
+            .any(|(chunk, mask)| large.chunks.get(chunk).is_some_and(|m| m & mask != 0))
+    }
+
+    /// Adds all voxels of `other`.
+    pub fn union_with(&mut self, other: &Voxels) {
+        for (chunk, mask) in &other.chunks {
+            let m = self.chunks.entry(chunk.clone()).or_default();
+            self.len += (mask & !*m).count_ones() as usize;
+            *m |= mask;
+        }
+    }
+
+    /// Removes all voxels of `other`.
+    pub fn difference_with(&mut self, other: &Voxels) {
+        for (chunk, mask) in &other.chunks {
+            if let Some(m) = self.chunks.get_mut(chunk) {
+                self.len -= (*m & mask).count_ones() as usize;
+                *m &= !mask;
+                if *m == 0 {
+                    self.chunks.remove(chunk);
+                }
+            }
+        }
+    }
+
+    pub fn union(&self, other: &Voxels) -> Voxels {
+        let mut res = self.clone();
+        res.union_with(other);
+        res
+    }
+
+    pub fn difference(&self, other: &Voxels) -> Voxels {
+        let mut res = self.clone();
+        res.difference_with(other);
+        res
+    }
+
+    /// Returns the voxels in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = Coordinate> + '_ {
+        self.chunks.iter().flat_map(|(chunk, mask)| {
+            (0..64)
+                .filter(move |bit| mask >> bit & 1 == 1)
+                .map(move |bit| {
+                    Coordinate::new(
+                        chunk.x * CHUNK_SIZE + bit % CHUNK_SIZE,
+                        chunk.y * CHUNK_SIZE + bit / CHUNK_SIZE % CHUNK_SIZE,
+                        chunk.z * CHUNK_SIZE + bit / (CHUNK_SIZE * CHUNK_SIZE),
+                    )
+                })
+        })
+    }
+}
+
+impl<'a> FromIterator<&'a Coordinate> for Voxels {
+    fn from_iter<I: IntoIterator<Item = &'a Coordinate>>(iter: I) -> Self {
+        let mut voxels = Self::new();
+        for pos in iter {
+            voxels.insert(pos);
+        }
+        voxels
+    }
+}
+
+impl FromIterator<Coordinate> for Voxels {
+    fn from_iter<I: IntoIterator<Item = Coordinate>>(iter: I) -> Self {
+        let mut voxels = Self::new();
+        for pos in iter {
+            voxels.insert(&pos);
+        }
+        voxels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Voxels;
+    use crate::program::Coordinate;
+
+    #[test]
+    fn test_voxels() {
+        let line = |x: i32, zs: std::ops::Range<i32>| -> Voxels {
+            zs.map(|z| Coordinate::new(x, -1, z)).collect()
+        };
+        let mut v1 = line(0, -3..6);
+        let v2 = line(0, 5..9);
+        let v3 = line(1, -3..9);
+        assert_eq!(v1.len(), 9);
+        assert!(v1.contains(&Coordinate::new(0, -1, -3)));
+        assert!(!v1.contains(&Coordinate::new(0, -1, 6)));
+        assert!(v1.is_collide(&v2) && v2.is_collide(&v1));
+        assert!(!v1.is_collide(&v3));
+
+        let union = v1.union(&v2);
+        assert_eq!(union, line(0, -3..9));
+        assert_eq!(union.difference(&v2), line(0, -3..5));
+        assert_eq!(union.difference(&union), Voxels::new());
+
+        let mut blocks: Vec<_> = union.iter().collect();
+        blocks.sort_by_key(|b| b.z);
+        assert_eq!(
+            blocks,
+            (-3..9)
+                .map(|z| Coordinate::new(0, -1, z))
+                .collect::<Vec<_>>()
+        );
+
+        assert!(!v1.insert(&Coordinate::new(0, -1, 0)));
+        assert!(v1.remove(&Coordinate::new(0, -1, 0)));
+        assert!(!v1.remove(&Coordinate::new(0, -1, 0)));
+        assert_eq!(v1.len(), 8);
+    }
+}
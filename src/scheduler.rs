@@ -1,10 +1,30 @@
+pub mod annealing_scheduler;
+pub mod backfilling_scheduler;
+pub mod batching;
+pub mod beam_search_scheduler;
 pub mod corner_greedy_scheduler;
 pub mod greedy_scheduler;
+pub mod latency;
+pub mod lp_dump;
 pub mod lp_scheduler;
+pub mod parallel;
+pub mod queue;
+pub mod registry;
+pub mod skyline_scheduler;
+pub mod timing;
 
+pub use annealing_scheduler::AnnealingScheduler;
+pub use backfilling_scheduler::BackfillingScheduler;
+pub use batching::BatchRound;
+pub use beam_search_scheduler::BeamSearchScheduler;
 pub use corner_greedy_scheduler::CornerGreedyScheduler;
 pub use greedy_scheduler::GreedyScheduler;
+pub use latency::{LatencyPredictor, LatencyRound};
 pub use lp_scheduler::LPScheduler;
+pub use queue::{JobQueue, QueuePolicy};
+pub use registry::{SchedulerParams, SchedulerRegistry};
+pub use skyline_scheduler::SkylineScheduler;
+pub use timing::SchedulingTimer;
 
 use crate::environment::Environment;
 use crate::job::{Job, JobID};
@@ -12,14 +32,6 @@ use crate::program::{Coordinate, Cuboid, Polycube, Program, ProgramFormat};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
-#[serde(rename_all = "lowercase")]
-pub enum SchedulerKind {
-    Greedy,
-    CornerGreedy,
-    LP,
-}
-
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Schedule {
     pub x: i32,
@@ -41,23 +53,23 @@ impl Schedule {
     }
 }
 
+fn flip_and_rotate(x: i32, y: i32, schedule: &Schedule) -> (i32, i32) {
+    let (x, y) = if schedule.flip { (-x, y) } else { (x, y) };
+    match schedule.rotate {
+        0 => (x, y),
+        1 => (-y, x),
+        2 => (-x, y),
+        _ => (y, -x),
+    }
+}
+
 /// Note: flip -> rotate -> (adjust coordinates) -> shift
 pub fn apply_schedule_to_polycube(polycube: &Polycube, schedule: &Schedule) -> Polycube {
     let mut blocks = Vec::new();
     let mut min_x = i32::MAX;
     let mut min_y = i32::MAX;
     for block in polycube.blocks() {
-        let (x, y) = if schedule.flip {
-            (-block.x, block.y)
-        } else {
-            (block.x, block.y)
-        };
-        let (x, y) = match schedule.rotate {
-            0 => (x, y),
-            1 => (-y, x),
-            2 => (-x, y),
-            _ => (y, -x),
-        };
+        let (x, y) = flip_and_rotate(block.x, block.y, schedule);
         min_x = i32::min(min_x, x);
         min_y = i32::min(min_y, y);
         blocks.push(Coordinate::new(x, y, block.z));
@@ -98,6 +110,31 @@ pub fn apply_schedule(program: &Program, schedule: &Schedule) -> Program {
             let scheduled = apply_schedule_to_polycube(polycube, schedule);
             Program::new(ProgramFormat::Polycube(scheduled))
         }
+        ProgramFormat::Cuboid(cuboids) if cuboids.len() > 1 => {
+            // The relative positions of cuboids are flipped and rotated in the same way as
+            // polycubes, keeping the position of the bounding box.
+            let bbox = program.bounding_box();
+            let corners: Vec<_> = cuboids
+                .iter()
+                .map(|c| {
+                    let (xa, ya) = flip_and_rotate(c.x1(), c.y1(), schedule);
+                    let (xb, yb) = flip_and_rotate(c.x2() - 1, c.y2() - 1, schedule);
+                    (i32::min(xa, xb), i32::min(ya, yb))
+                })
+                .collect();
+            let min_x = corners.iter().map(|(x, _)| *x).min().unwrap();
+            let min_y = corners.iter().map(|(_, y)| *y).min().unwrap();
+            let cuboids = cuboids
+                .iter()
+                .zip(corners)
+                .map(|(c, (x, y))| {
+                    let pos = Coordinate::new(x - min_x + bbox.x1(), y - min_y + bbox.y1(), c.z1());
+                    let moved = Cuboid::new(pos, c.size_x(), c.size_y(), c.size_z());
+                    apply_schedule_to_cuboid(&moved, schedule)
+                })
+                .collect();
+            Program::new(ProgramFormat::Cuboid(cuboids))
+        }
         ProgramFormat::Cuboid(cuboids) => {
             let cuboids = cuboids
                 .iter()
@@ -108,15 +145,49 @@ pub fn apply_schedule(program: &Program, schedule: &Schedule) -> Program {
     }
 }
 
+/// Returns the cost of the placement `n * max_i z2[i] + sum_i z2[i]`, where `z2[i]` is the end
+/// point of the i-th program measured from `scheduled_point` and `n` is the number of programs.
+pub(crate) fn placement_cost(placed: &[Program], scheduled_point: i32) -> u64 {
+    let ends: Vec<_> = placed
+        .iter()
+        .map(|p| (p.z2() - scheduled_point).max(0) as u64)
+        .collect();
+    let max_end = ends.iter().max().copied().unwrap_or(0);
+    ends.len() as u64 * max_end + ends.iter().sum::<u64>()
+}
+
 pub trait Scheduler {
     fn add_job(&mut self, job: Job);
     fn run(&mut self, env: &Environment) -> Vec<(JobID, Schedule)>;
+
+    /// Returns scheduler-specific statistics, which are included in the simulation result.
+    fn report(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Returns the predicted and actual latency of each scheduling if the scheduler predicts
+    /// it to decide the scheduling point.
+    fn latency_rounds(&self) -> Option<&[LatencyRound]> {
+        None
+    }
+
+    /// Returns the latency of the last scheduling in cycles measured by `SchedulingTimer`. In
+    /// the `modelled` scheduling time, the simulator uses it instead of the wall-clock time.
+    fn scheduling_cycles(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the batch size chosen in each scheduling if the adaptive batch size is
+    /// configured.
+    fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        None
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::program::{Coordinate, Polycube, Program, ProgramFormat};
-    use crate::scheduler::{apply_schedule, Schedule};
+    use crate::program::{Coordinate, Cuboid, Polycube, Program, ProgramFormat};
+    use crate::scheduler::{apply_schedule, placement_cost, Schedule};
 
     #[test]
     fn test_apply_schedule() {
@@ -130,4 +201,38 @@ mod test {
         let expected = Polycube::new(vec![Coordinate::new(2, 10, 3), Coordinate::new(1, 11, 3)]);
         assert_eq!(*actual, expected);
     }
+
+    #[test]
+    fn test_apply_schedule_to_k_cuboid() {
+        let p = Program::new(ProgramFormat::Cuboid(vec![
+            Cuboid::new(Coordinate::new(0, 0, 0), 2, 1, 1),
+            Cuboid::new(Coordinate::new(1, 0, 1), 1, 1, 1),
+        ]));
+        let s = Schedule::new(5, 5, 10, 1, false);
+        let scheduled = apply_schedule(&p, &s);
+        let actual = scheduled.cuboid().unwrap();
+        let expected = vec![
+            Cuboid::new(Coordinate::new(5, 5, 10), 1, 2, 1),
+            Cuboid::new(Coordinate::new(5, 6, 11), 1, 1, 1),
+        ];
+        assert_eq!(*actual, expected);
+    }
+
+    #[test]
+    fn test_placement_cost() {
+        let p1 = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(0, 0, 10),
+            1,
+            1,
+            2,
+        )]));
+        let p2 = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(1, 0, 12),
+            1,
+            1,
+            3,
+        )]));
+        // ends: 2, 5
+        assert_eq!(placement_cost(&[p1, p2], 10), 2 * 5 + 2 + 5);
+    }
 }
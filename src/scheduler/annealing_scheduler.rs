@@ -0,0 +1,399 @@
+use crate::config::SimulationConfig;
+use crate::environment::Environment;
+use crate::job::Job;
+use crate::program::{is_overlap, Coordinate, Program};
+use crate::scheduler::corner_greedy_scheduler::{
+    collect_location_candidates, create_location_candidate, is_overlap_with_moves,
+    place_jobs_at_corners,
+};
+use crate::scheduler::parallel::CandidateEvaluator;
+use crate::scheduler::{
+    apply_schedule, placement_cost, BatchRound, JobID, JobQueue, LatencyPredictor, LatencyRound,
+    Schedule, Scheduler, SchedulingTimer,
+};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use std::time::{Duration, Instant};
+
+/// The default number of iterations per job in a batch.
+const DEFAULT_ITERATIONS_PER_JOB: u32 = 200;
+
+/// One in `REORDER_MOVE_RATIO` moves re-places the whole batch in a different order.
+const REORDER_MOVE_RATIO: u32 = 5;
+
+/// The result of the local search in a scheduling round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnnealingRound {
+    pub batch_size: usize,
+    /// The cost of the initial solution given by the corner greedy algorithm.
+    pub initial_cost: u64,
+    /// The cost of the returned solution.
+    pub final_cost: u64,
+    pub iterations: u64,
+    pub accepted_moves: u64,
+}
+
+/// A scheduler that improves the placement of `CornerGreedyScheduler` by simulated annealing.
+///
+/// The cost of a placement is `n * max_i z2[i] + sum_i z2[i]`, where `z2[i]` is the end point of
+/// the i-th program measured from the scheduling point and `n` is the batch size. A move either
+/// relocates a single program or swaps two programs in the order given to the corner greedy
+/// algorithm and places the whole batch again. The search
+/// stops when either `scheduler.annealing_iterations` or `scheduler.time_limit` is reached.
+/// Note that the search is reproducible with `scheduler.seed` as long as the time limit is not
+/// reached.
+pub struct AnnealingScheduler {
+    job_list: JobQueue,
+    config: SimulationConfig,
+    rng: StdRng,
+    latency_predictor: LatencyPredictor,
+    timer: SchedulingTimer,
+    evaluator: CandidateEvaluator,
+    rounds: Vec<AnnealingRound>,
+}
+
+impl AnnealingScheduler {
+    pub fn new(config: SimulationConfig) -> Self {
+        let rng = match config.scheduler.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            job_list: JobQueue::new(&config.scheduler),
+            latency_predictor: LatencyPredictor::new(config.scheduler.latency_predictor.clone()),
+            timer: SchedulingTimer::new(&config),
+            evaluator: CandidateEvaluator::new(config.scheduler.num_threads),
+            config,
+            rng,
+            rounds: Vec::new(),
+        }
+    }
+
+    /// Returns candidates of the next schedule of the i-th program. The best feasible one among
+    /// them is used as the next solution.
+    fn neighbors(
+        &mut self,
+        schedule: &Schedule,
+        corners: &[Coordinate],
+        placed: &[Program],
+        i: usize,
+    ) -> Vec<Schedule> {
+        // corners of running programs and the other placed programs
+        let corners = corners.iter().cloned().chain(
+            placed
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .flat_map(|(_, p)| create_location_candidate(p)),
+        );
+
+        let mut next = schedule.clone();
+        match self.rng.gen_range(0..4) {
+            // shift
+            0 => {
+                next.x += self.rng.gen_range(-2..=2);
+                next.y += self.rng.gen_range(-2..=2);
+                next.z += self.rng.gen_range(-4..=4);
+                vec![next]
+            }
+            // rotate
+            1 => {
+                next.rotate = (next.rotate + self.rng.gen_range(1..4)) % 4;
+                vec![next]
+            }
+            // move to a random corner (no move if there are no corners)
+            2 => {
+                let corners: Vec<_> = corners.collect();
+                if corners.is_empty() {
+                    return Vec::new();
+                }
+                let pos = &corners[self.rng.gen_range(0..corners.len())];
+                next.x = pos.x;
+                next.y = pos.y;
+                next.z = pos.z;
+                vec![next]
+            }
+            // reinsert to the best corner
+            _ => corners
+                .flat_map(|pos| {
+                    (0..2).map(move |rot| Schedule::new(pos.x, pos.y, pos.z, rot, false))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Scheduler for AnnealingScheduler {
+    fn add_job(&mut self, job: Job) {
+        self.job_list.push(job);
+    }
+
+    fn run(&mut self, env: &Environment) -> Vec<(JobID, Schedule)> {
+        let est_scheduling_cost = self.latency_predictor.predict();
+
+        self.timer.start();
+        // The time limit is always in the wall-clock time.
+        let start = Instant::now();
+
+        let scheduled_point = env.global_pc() + est_scheduling_cost;
+
+        let jobs = self.job_list.take_batch();
+        if jobs.is_empty() {
+            return Vec::new();
+        }
+
+        let mut schedules = place_jobs_at_corners(env, &self.evaluator, scheduled_point, &jobs);
+        let mut placed: Vec<_> = jobs
+            .iter()
+            .zip(&schedules)
+            .map(|(job, s)| apply_schedule(&job.program, s))
+            .collect();
+        let initial_cost = placement_cost(&placed, scheduled_point as i32);
+
+        let corners = collect_location_candidates(env, scheduled_point);
+        let max_iterations = self
+            .config
+            .scheduler
+            .annealing_iterations
+            .unwrap_or(DEFAULT_ITERATIONS_PER_JOB * jobs.len() as u32);
+        let time_limit = self
+            .config
+            .scheduler
+            .time_limit
+            .map(|sec| Duration::from_secs(sec.into()));
+
+        // Delaying the last program by `l` increases the cost by `(n + 1) * l`. The initial
+        // temperature is set so that such a move with the average program length is accepted
+        // with the probability 1/e at first.
+        let init_temp = placed
+            .iter()
+            .map(|p| p.burst_time() as f64)
+            .sum::<f64>()
+            .max(1.)
+            * (placed.len() + 1) as f64
+            / placed.len() as f64;
+        let final_temp = 0.01;
+
+        let mut order: Vec<_> = (0..jobs.len()).collect();
+        let mut cost = initial_cost;
+        let mut best = (cost, schedules.clone());
+        let mut iterations = 0;
+        let mut accepted_moves = 0;
+        while iterations < max_iterations as u64 {
+            let progress = {
+                let by_iter = iterations as f64 / max_iterations as f64;
+                let by_time = time_limit.map_or(0., |limit| {
+                    start.elapsed().as_secs_f64() / limit.as_secs_f64()
+                });
+                f64::max(by_iter, by_time)
+            };
+            if progress >= 1. {
+                break;
+            }
+            iterations += 1;
+            let temp = init_temp * (final_temp / init_temp).powf(progress);
+
+            // Swap the order of two jobs and place all jobs again by the corner greedy algorithm
+            if jobs.len() >= 2 && self.rng.gen_range(0..REORDER_MOVE_RATIO) == 0 {
+                let mut next_order = order.clone();
+                let i = self.rng.gen_range(0..jobs.len());
+                let j = self.rng.gen_range(0..jobs.len());
+                next_order.swap(i, j);
+                let ordered_jobs: Vec<_> = next_order.iter().map(|&k| jobs[k].clone()).collect();
+                let mut next_schedules = schedules.clone();
+                for (k, s) in next_order.iter().zip(place_jobs_at_corners(
+                    env,
+                    &self.evaluator,
+                    scheduled_point,
+                    &ordered_jobs,
+                )) {
+                    next_schedules[*k] = s;
+                }
+                let next_placed: Vec<_> = jobs
+                    .iter()
+                    .zip(&next_schedules)
+                    .map(|(job, s)| apply_schedule(&job.program, s))
+                    .collect();
+                let next_cost = placement_cost(&next_placed, scheduled_point as i32);
+                let delta = next_cost as f64 - cost as f64;
+                if delta <= 0. || self.rng.gen::<f64>() < f64::exp(-delta / temp) {
+                    order = next_order;
+                    schedules = next_schedules;
+                    placed = next_placed;
+                    cost = next_cost;
+                    accepted_moves += 1;
+                    if cost < best.0 {
+                        best = (cost, schedules.clone());
+                    }
+                }
+                continue;
+            }
+
+            let i = self.rng.gen_range(0..jobs.len());
+            let candidates = self.neighbors(&schedules[i], &corners, &placed, i);
+            let is_feasible = |program: &Program| {
+                placed
+                    .iter()
+                    .enumerate()
+                    .all(|(j, p)| i == j || !is_overlap(program, p))
+                    && !is_overlap_with_moves(program, env.defrag_move_areas())
+                    && env.can_issue(program)
+            };
+            let mut best_move: Option<(Schedule, Program)> = None;
+            for mut next in candidates {
+                if next.z < scheduled_point as i32 {
+                    continue;
+                }
+                let mut program = apply_schedule(&jobs[i].program, &next);
+                if !is_feasible(&program) {
+                    continue;
+                }
+                // Drop the program as low as possible
+                while next.z > scheduled_point as i32 {
+                    let lower = Schedule::new(next.x, next.y, next.z - 1, next.rotate, next.flip);
+                    let lower_program = apply_schedule(&jobs[i].program, &lower);
+                    if !is_feasible(&lower_program) {
+                        break;
+                    }
+                    next = lower;
+                    program = lower_program;
+                }
+                if best_move
+                    .as_ref()
+                    .is_none_or(|(s, _)| (next.z, next.x + next.y) < (s.z, s.x + s.y))
+                {
+                    best_move = Some((next, program));
+                }
+            }
+            let Some((next, program)) = best_move else {
+                continue;
+            };
+
+            let prev = std::mem::replace(&mut placed[i], program);
+            let next_cost = placement_cost(&placed, scheduled_point as i32);
+            let delta = next_cost as f64 - cost as f64;
+            if delta <= 0. || self.rng.gen::<f64>() < f64::exp(-delta / temp) {
+                schedules[i] = next;
+                cost = next_cost;
+                accepted_moves += 1;
+                if cost < best.0 {
+                    best = (cost, schedules.clone());
+                }
+            } else {
+                placed[i] = prev;
+            }
+        }
+
+        let (final_cost, schedules) = best;
+        tracing::debug!(
+            "Annealing: cost {} -> {} ({} iterations, {} accepted)",
+            initial_cost,
+            final_cost,
+            iterations,
+            accepted_moves
+        );
+        self.rounds.push(AnnealingRound {
+            batch_size: jobs.len(),
+            initial_cost,
+            final_cost,
+            iterations,
+            accepted_moves,
+        });
+
+        self.timer.add_work(iterations);
+        let elapsed = self.timer.stop(jobs.len());
+        self.latency_predictor.observe(elapsed);
+        self.job_list.observe_latency(elapsed, est_scheduling_cost);
+
+        jobs.iter().map(|job| job.id).zip(schedules).collect()
+    }
+
+    fn report(&self) -> Option<serde_json::Value> {
+        let initial_cost_sum: u64 = self.rounds.iter().map(|r| r.initial_cost).sum();
+        let final_cost_sum: u64 = self.rounds.iter().map(|r| r.final_cost).sum();
+        Some(serde_json::json!({
+            "initial_cost_sum": initial_cost_sum,
+            "final_cost_sum": final_cost_sum,
+            "rounds": self.rounds,
+        }))
+    }
+
+    fn latency_rounds(&self) -> Option<&[LatencyRound]> {
+        Some(self.latency_predictor.rounds())
+    }
+
+    fn scheduling_cycles(&self) -> Option<u64> {
+        self.timer.last_cycles()
+    }
+
+    fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        self.job_list.batch_rounds()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AnnealingScheduler;
+    use crate::config::SimulationConfig;
+    use crate::environment::Environment;
+    use crate::job::Job;
+    use crate::program::{Coordinate, Cuboid, Program, ProgramFormat};
+    use crate::scheduler::{Schedule, Scheduler};
+    use crate::test_utils;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_annealing_neighbors() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.scheduler.seed = Some(0);
+        let mut scheduler = AnnealingScheduler::new(config);
+
+        // Without corners, every neighbor is a shift or a rotation of the schedule.
+        let schedule = Schedule::new(1, 1, 1, 3, false);
+        let mut rotations = HashSet::new();
+        for _ in 0..200 {
+            for next in scheduler.neighbors(&schedule, &[], &[], 0) {
+                assert!((0..4).contains(&next.rotate));
+                if (next.x, next.y, next.z) == (1, 1, 1) && next.rotate != 3 {
+                    rotations.insert(next.rotate);
+                }
+            }
+        }
+        assert_eq!(rotations, HashSet::from([0, 1, 2]));
+    }
+
+    #[test]
+    fn test_annealing_improves_corner_greedy() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.size_x = 2;
+        config.size_y = 2;
+        config.scheduler.batch_size = None;
+        config.scheduler.time_limit = None;
+        config.scheduler.seed = Some(0);
+        let env = Environment::new(config.clone());
+
+        // The corner greedy algorithm places the 2x2 program on top of the first one, and thus
+        // the last one has to wait for it.
+        let cuboid = |sx, sy, sz| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                sx,
+                sy,
+                sz,
+            )]))
+        };
+        let mut scheduler = AnnealingScheduler::new(config);
+        scheduler.add_job(Job::new(0, 0, cuboid(1, 2, 1)));
+        scheduler.add_job(Job::new(1, 0, cuboid(2, 2, 3)));
+        scheduler.add_job(Job::new(2, 0, cuboid(1, 2, 3)));
+        let schedules = scheduler.run(&env);
+
+        assert_eq!(schedules.len(), 3);
+        let round = &scheduler.rounds[0];
+        assert_eq!(round.initial_cost, 3 * 7 + (1 + 4 + 7));
+        assert!(round.final_cost < round.initial_cost);
+    }
+}
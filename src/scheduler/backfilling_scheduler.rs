@@ -0,0 +1,220 @@
+use crate::config::SimulationConfig;
+use crate::environment::Environment;
+use crate::job::Job;
+use crate::program::{Coordinate, Program, SpatialIndex};
+use crate::scheduler::corner_greedy_scheduler::{
+    collect_location_candidates, create_location_candidate, find_best_location,
+};
+use crate::scheduler::parallel::CandidateEvaluator;
+use crate::scheduler::{
+    apply_schedule, BatchRound, JobID, JobQueue, LatencyPredictor, LatencyRound, Schedule,
+    Scheduler, SchedulingTimer,
+};
+
+/// A scheduler based on EASY backfilling.
+///
+/// Jobs are examined in the queue order. A job is issued if it can start at the scheduling point.
+/// Otherwise, the first such job (the head of the queue) reserves the lowest location where it
+/// can be placed, and the later jobs can be issued only if they start at the scheduling point
+/// without overlapping with the reserved area. Jobs that are not issued are kept in the queue and
+/// examined again in the next scheduling. If no job can start at the scheduling point, the head
+/// of the queue is issued at the reserved location.
+///
+/// Locations are chosen in the same way as `CornerGreedyScheduler`. Only the first
+/// `scheduler.batch_size` jobs (or the adaptive batch size) in the queue are examined in each
+/// scheduling.
+pub struct BackfillingScheduler {
+    job_list: JobQueue,
+    latency_predictor: LatencyPredictor,
+    timer: SchedulingTimer,
+    evaluator: CandidateEvaluator,
+    backfilled_jobs: u64,
+}
+
+impl BackfillingScheduler {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self {
+            job_list: JobQueue::new(&config.scheduler),
+            latency_predictor: LatencyPredictor::new(config.scheduler.latency_predictor.clone()),
+            timer: SchedulingTimer::new(&config),
+            evaluator: CandidateEvaluator::new(config.scheduler.num_threads),
+            backfilled_jobs: 0,
+        }
+    }
+}
+
+/// Places the program at the best location, which must be at the scheduling point if
+/// `scheduled_point` is given. The used location candidate is replaced with the corners of the
+/// placed program.
+fn place(
+    env: &Environment,
+    evaluator: &CandidateEvaluator,
+    location_candidates: &mut Vec<Coordinate>,
+    placed: &SpatialIndex,
+    program: &Program,
+    scheduled_point: Option<u64>,
+) -> Option<(Schedule, Program)> {
+    let (i, schedule) = if let Some(scheduled_point) = scheduled_point {
+        let now: Vec<_> = location_candidates
+            .iter()
+            .filter(|pos| pos.z as u64 == scheduled_point)
+            .cloned()
+            .collect();
+        let (i, schedule) = find_best_location(env, evaluator, &now, placed, program)?;
+        let i = location_candidates
+            .iter()
+            .position(|pos| *pos == now[i])
+            .unwrap();
+        (i, schedule)
+    } else {
+        find_best_location(env, evaluator, location_candidates, placed, program)?
+    };
+    let scheduled_program = apply_schedule(program, &schedule);
+    location_candidates.remove(i);
+    location_candidates.extend(create_location_candidate(&scheduled_program));
+    Some((schedule, scheduled_program))
+}
+
+impl Scheduler for BackfillingScheduler {
+    fn add_job(&mut self, job: Job) {
+        self.job_list.push(job);
+    }
+
+    fn run(&mut self, env: &Environment) -> Vec<(JobID, Schedule)> {
+        let est_scheduling_cost = self.latency_predictor.predict();
+
+        self.timer.start();
+
+        let scheduled_point = env.global_pc() + est_scheduling_cost;
+
+        let jobs = self.job_list.take_batch();
+        let batch_size = jobs.len();
+        let mut location_candidates = collect_location_candidates(env, scheduled_point);
+        let mut placed = SpatialIndex::new(); // issued programs and the reserved one
+        let mut reservation: Option<(usize, Schedule)> = None; // (id in `placed`, schedule)
+        let mut res = Vec::new();
+        let mut waiting = Vec::new();
+        for job in jobs {
+            if let Some((_, reserved)) = &reservation {
+                let backfill = place(
+                    env,
+                    &self.evaluator,
+                    &mut location_candidates,
+                    &placed,
+                    &job.program,
+                    Some(scheduled_point),
+                );
+                if let Some((schedule, program)) = backfill {
+                    tracing::debug!(
+                        "Backfill job {} before the reservation at z = {}",
+                        job.id,
+                        reserved.z
+                    );
+                    self.backfilled_jobs += 1;
+                    placed.insert(program);
+                    res.push((job.id, schedule));
+                } else {
+                    waiting.push(job);
+                }
+                continue;
+            }
+
+            let (schedule, program) = place(
+                env,
+                &self.evaluator,
+                &mut location_candidates,
+                &placed,
+                &job.program,
+                None,
+            )
+            .expect("The program is larger than the chip");
+            let id = placed.insert(program);
+            if schedule.z as u64 == scheduled_point {
+                res.push((job.id, schedule));
+            } else {
+                reservation = Some((id, schedule));
+                waiting.push(job);
+            }
+        }
+
+        if let Some((id, schedule)) = reservation {
+            if res.is_empty() {
+                // Nothing can start now, so the head of the queue is issued.
+                res.push((waiting.remove(0).id, schedule));
+            } else {
+                tracing::debug!("Reserve z = {} for job {}", schedule.z, waiting[0].id);
+                placed.remove(id);
+            }
+        }
+        // The jobs not issued are returned to the head of the queue.
+        self.job_list.return_jobs(waiting);
+
+        self.timer.add_work(self.evaluator.take_evaluated());
+        let elapsed = self.timer.stop(batch_size);
+        self.latency_predictor.observe(elapsed);
+        self.job_list.observe_latency(elapsed, est_scheduling_cost);
+
+        res
+    }
+
+    fn report(&self) -> Option<serde_json::Value> {
+        Some(serde_json::json!({
+            "backfilled_jobs": self.backfilled_jobs,
+        }))
+    }
+
+    fn latency_rounds(&self) -> Option<&[LatencyRound]> {
+        Some(self.latency_predictor.rounds())
+    }
+
+    fn scheduling_cycles(&self) -> Option<u64> {
+        self.timer.last_cycles()
+    }
+
+    fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        self.job_list.batch_rounds()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BackfillingScheduler;
+    use crate::config::SimulationConfig;
+    use crate::environment::Environment;
+    use crate::job::Job;
+    use crate::program::{Coordinate, Cuboid, Program, ProgramFormat};
+    use crate::scheduler::{Schedule, Scheduler};
+    use crate::test_utils;
+
+    #[test]
+    fn test_backfilling() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.size_x = 2;
+        config.size_y = 2;
+        config.scheduler.batch_size = None;
+        let mut env = Environment::new(config.clone());
+
+        let cuboid = |x, z, sx, sy, sz| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(x, 0, z),
+                sx,
+                sy,
+                sz,
+            )]))
+        };
+        assert!(env.issue_program(&cuboid(0, 0, 1, 2, 10)));
+
+        let mut scheduler = BackfillingScheduler::new(config);
+        // The head reserves z = 10 because it requires the whole chip.
+        scheduler.add_job(Job::new(0, 0, cuboid(0, 0, 2, 2, 1)));
+        // This job can be executed before the reservation.
+        scheduler.add_job(Job::new(1, 0, cuboid(0, 0, 1, 2, 3)));
+        // This job overlaps with the reservation if it is issued now.
+        scheduler.add_job(Job::new(2, 0, cuboid(0, 0, 1, 2, 20)));
+        let schedules = scheduler.run(&env);
+
+        assert_eq!(schedules, vec![(1, Schedule::new(1, 0, 0, 0, false))]);
+        let waiting: Vec<_> = scheduler.job_list.iter().map(|job| job.id).collect();
+        assert_eq!(waiting, vec![0, 2]);
+    }
+}
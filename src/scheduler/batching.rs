@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::SchedulerConfig;
+use crate::error::QMPError;
+
+/// The bounds and the latency target of the adaptive batch size.
+///
+/// The batch size is doubled when jobs were left in the queue and the last scheduling took at
+/// most half of `target_latency`, and halved when the last scheduling exceeded `target_latency`
+/// or suspended the execution (i.e., took longer than predicted).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdaptiveBatchConfig {
+    /// 1 by default
+    pub min_batch_size: Option<u32>,
+    pub max_batch_size: u32,
+    /// The latency budget of each scheduling in cycles
+    pub target_latency: u64,
+}
+
+impl AdaptiveBatchConfig {
+    pub fn min_batch_size(&self) -> u32 {
+        self.min_batch_size.unwrap_or(1)
+    }
+
+    /// Checks that `0 < min_batch_size <= max_batch_size`.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let (min, max) = (self.min_batch_size(), self.max_batch_size);
+        if 0 < min && min <= max {
+            Ok(())
+        } else {
+            Err(QMPError::InvalidBatchSizeBounds { min, max }.into())
+        }
+    }
+}
+
+/// The batch size chosen in a scheduling and its outcome.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchRound {
+    pub batch_size: u32,
+    /// The number of waiting jobs when the batch was taken
+    pub queue_len: usize,
+    /// The latency of the scheduling in cycles
+    pub latency: Option<u64>,
+    /// True if the execution was suspended until the scheduling finished
+    pub suspended: bool,
+}
+
+/// Decides the batch size of each scheduling, which is `batch_size` unless the adaptive batch
+/// size is configured.
+pub struct BatchSizer {
+    config: Option<AdaptiveBatchConfig>,
+    /// The current batch size (`None` means all waiting jobs)
+    batch_size: Option<u32>,
+    rounds: Vec<BatchRound>,
+    /// True if the last round waits for its latency
+    is_observing: bool,
+}
+
+impl BatchSizer {
+    pub fn new(config: &SchedulerConfig) -> Self {
+        let adaptive = config.adaptive_batch.clone();
+        let batch_size = match &adaptive {
+            Some(adaptive) => {
+                // The bounds are validated when the config is loaded.
+                let min = adaptive.min_batch_size();
+                let initial = config.batch_size.unwrap_or(min);
+                Some(initial.clamp(min, adaptive.max_batch_size))
+            }
+            None => config.batch_size,
+        };
+        Self {
+            config: adaptive,
+            batch_size,
+            rounds: Vec::new(),
+            is_observing: false,
+        }
+    }
+
+    /// Returns the batch size of the next scheduling with `queue_len` waiting jobs. Schedulings
+    /// of an empty queue are not recorded.
+    pub fn next(&mut self, queue_len: usize) -> Option<u32> {
+        self.is_observing = self.config.is_some() && queue_len > 0;
+        if self.is_observing {
+            let batch_size = self.batch_size.unwrap();
+            tracing::debug!(
+                "Batch size = {} (#waiting jobs = {})",
+                batch_size,
+                queue_len
+            );
+            self.rounds.push(BatchRound {
+                batch_size,
+                queue_len,
+                latency: None,
+                suspended: false,
+            });
+        }
+        self.batch_size
+    }
+
+    /// Adapts the batch size to the latency of the last scheduling, which was predicted to be
+    /// `predicted` cycles.
+    pub fn observe(&mut self, latency: u64, predicted: u64) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        if !std::mem::take(&mut self.is_observing) {
+            return;
+        }
+        let round = self.rounds.last_mut().unwrap();
+        round.latency = Some(latency);
+        round.suspended = latency > predicted;
+
+        let min = config.min_batch_size();
+        let batch_size = self.batch_size.unwrap();
+        let next = if round.suspended || latency > config.target_latency {
+            batch_size / 2
+        } else if round.queue_len > batch_size as usize && latency * 2 <= config.target_latency {
+            batch_size * 2
+        } else {
+            batch_size
+        };
+        self.batch_size = Some(next.clamp(min, config.max_batch_size));
+    }
+
+    /// Returns the batch size of each scheduling if the adaptive batch size is configured.
+    pub fn rounds(&self) -> Option<&[BatchRound]> {
+        self.config.as_ref().map(|_| self.rounds.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AdaptiveBatchConfig, BatchSizer};
+    use crate::config::SimulationConfig;
+    use crate::test_utils;
+
+    #[test]
+    fn test_adaptive_batch_size() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.scheduler.batch_size = Some(3);
+        assert_eq!(BatchSizer::new(&config.scheduler).next(10), Some(3));
+
+        config.scheduler.adaptive_batch = Some(AdaptiveBatchConfig {
+            min_batch_size: Some(2),
+            max_batch_size: 8,
+            target_latency: 10,
+        });
+        let mut sizer = BatchSizer::new(&config.scheduler);
+        let mut sizes = Vec::new();
+        // (#waiting jobs, latency, predicted latency)
+        for (queue_len, latency, predicted) in [
+            (10, 4, 5),   // fast with a long queue: grow
+            (10, 5, 5),   // grow
+            (20, 4, 5),   // capped at the maximum
+            (20, 11, 12), // over the target: shrink
+            (20, 6, 5),   // suspended: shrink
+            (2, 1, 5),    // no jobs left behind: keep
+            (20, 6, 6),   // not fast enough: keep
+        ] {
+            sizes.push(sizer.next(queue_len).unwrap());
+            sizer.observe(latency, predicted);
+        }
+        assert_eq!(sizes, vec![3, 6, 8, 8, 4, 2, 2]);
+        // An empty queue neither is recorded nor changes the batch size.
+        assert_eq!(sizer.next(0), Some(2));
+        sizer.observe(0, 5);
+        assert_eq!(sizer.next(20), Some(2));
+
+        let rounds = sizer.rounds().unwrap();
+        assert_eq!(rounds.len(), 8);
+        assert!(rounds[4].suspended && !rounds[3].suspended);
+        assert_eq!(rounds[7].latency, None);
+    }
+
+    #[test]
+    fn test_adaptive_batch_bounds() {
+        let mut adaptive = AdaptiveBatchConfig {
+            min_batch_size: None,
+            max_batch_size: 4,
+            target_latency: 10,
+        };
+        assert!(adaptive.validate().is_ok());
+        adaptive.min_batch_size = Some(5);
+        assert!(adaptive.validate().is_err());
+        adaptive.min_batch_size = Some(0);
+        assert!(adaptive.validate().is_err());
+    }
+}
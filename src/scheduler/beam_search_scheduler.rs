@@ -0,0 +1,370 @@
+use crate::config::SimulationConfig;
+use crate::environment::Environment;
+use crate::job::Job;
+use crate::program::{is_overlap, Coordinate, Program};
+use crate::scheduler::corner_greedy_scheduler::{
+    collect_location_candidates, create_location_candidate, is_overlap_with_moves,
+};
+use crate::scheduler::{
+    apply_schedule, placement_cost, BatchRound, JobID, JobQueue, LatencyPredictor, LatencyRound,
+    Schedule, Scheduler, SchedulingTimer,
+};
+
+use std::time::{Duration, Instant};
+
+/// The default beam width.
+const DEFAULT_BEAM_WIDTH: u32 = 8;
+
+/// A partial placement of the batch.
+#[derive(Debug, Clone)]
+struct BeamState {
+    schedules: Vec<Option<Schedule>>, // indexed by the position of the job in the batch
+    placed: Vec<Program>,
+    location_candidates: Vec<Coordinate>,
+    cost: u64,
+}
+
+impl BeamState {
+    /// Places the i-th job by `schedule`, where `scheduled_program` is the placed program.
+    fn place(
+        &mut self,
+        i: usize,
+        schedule: Schedule,
+        scheduled_program: Program,
+        scheduled_point: u64,
+    ) {
+        self.location_candidates
+            .extend(create_location_candidate(&scheduled_program));
+        self.placed.push(scheduled_program);
+        self.schedules[i] = Some(schedule);
+        self.cost = placement_cost(&self.placed, scheduled_point as i32);
+    }
+
+    /// Returns true if the two states place the same programs at the same positions, possibly
+    /// in different orders.
+    fn is_same_placement(&self, other: &BeamState) -> bool {
+        self.cost == other.cost
+            && self.placed.len() == other.placed.len()
+            && self.placed.iter().all(|p| other.placed.contains(p))
+    }
+}
+
+/// A scheduler that searches the placement of the batch by beam search.
+///
+/// In each step, every partial placement in the beam is extended by placing one of the remaining
+/// jobs at one of its location candidates (the corners of running and already placed programs)
+/// with one of two rotations. Then only `scheduler.beam_width` distinct partial placements with
+/// the least cost (see `AnnealingScheduler` for the definition) are kept for the next step. If no
+/// partial placement can be extended, a job is placed above the best one instead. With the beam
+/// width 1, the search is similar to `CornerGreedyScheduler` except that it also chooses the
+/// order of jobs. If `scheduler.time_limit` is reached, the remaining steps are done with the
+/// beam width 1.
+pub struct BeamSearchScheduler {
+    job_list: JobQueue,
+    config: SimulationConfig,
+    latency_predictor: LatencyPredictor,
+    timer: SchedulingTimer,
+}
+
+impl BeamSearchScheduler {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self {
+            job_list: JobQueue::new(&config.scheduler),
+            latency_predictor: LatencyPredictor::new(config.scheduler.latency_predictor.clone()),
+            timer: SchedulingTimer::new(&config),
+            config,
+        }
+    }
+}
+
+/// Returns all partial placements obtained by placing one more job in `state`.
+fn expand(
+    env: &Environment,
+    scheduled_point: u64,
+    jobs: &[Job],
+    state: &BeamState,
+) -> Vec<BeamState> {
+    let mut children = Vec::new();
+    for (i, job) in jobs.iter().enumerate() {
+        if state.schedules[i].is_some() {
+            continue;
+        }
+        for (k, candidate) in state.location_candidates.iter().enumerate() {
+            for rot in 0..2 {
+                let schedule = Schedule::new(candidate.x, candidate.y, candidate.z, rot, false);
+                let scheduled_program = apply_schedule(&job.program, &schedule);
+                let is_overlap = state
+                    .placed
+                    .iter()
+                    .any(|p| is_overlap(&scheduled_program, p));
+                if is_overlap
+                    || is_overlap_with_moves(&scheduled_program, env.defrag_move_areas())
+                    || !env.can_issue(&scheduled_program)
+                {
+                    continue;
+                }
+
+                let mut child = state.clone();
+                child.location_candidates.remove(k);
+                child.place(i, schedule, scheduled_program, scheduled_point);
+                children.push(child);
+            }
+        }
+    }
+    children
+}
+
+/// Returns at most `width` states with the least cost among `children`, skipping the states with
+/// the same placement as a better one (which are reached by placing the jobs in different
+/// orders).
+fn select_states(mut children: Vec<BeamState>, width: usize) -> Vec<BeamState> {
+    // Ties are broken by the positions of the placed programs so that the result is
+    // deterministic.
+    children.sort_by_cached_key(|state| {
+        let pos_sum: i64 = state
+            .schedules
+            .iter()
+            .flatten()
+            .map(|s| (s.z + s.x + s.y) as i64)
+            .sum();
+        (state.cost, pos_sum)
+    });
+    let mut selected: Vec<BeamState> = Vec::with_capacity(width);
+    for child in children {
+        if selected.len() == width {
+            break;
+        }
+        if !selected.iter().any(|state| state.is_same_placement(&child)) {
+            selected.push(child);
+        }
+    }
+    selected
+}
+
+/// Places the first remaining job of `state` above all running and placed programs, like
+/// `GreedyScheduler`. This is used when no state can be extended at its location candidates.
+fn place_above(
+    config: &SimulationConfig,
+    env: &Environment,
+    scheduled_point: u64,
+    jobs: &[Job],
+    state: &mut BeamState,
+) {
+    let i = state.schedules.iter().position(Option::is_none).unwrap();
+    let program = &jobs[i].program;
+    let top = env
+        .running_programs()
+        .iter()
+        .chain(&state.placed)
+        .map(|p| p.z2())
+        .fold(scheduled_point as i32, i32::max);
+    let (size_x, size_y) = (config.size_x as i32, config.size_y as i32);
+    for z in top.. {
+        for x in 0..size_x {
+            for y in 0..size_y {
+                for rot in 0..2 {
+                    let schedule = Schedule::new(x, y, z, rot, false);
+                    let scheduled_program = apply_schedule(program, &schedule);
+                    if !is_overlap_with_moves(&scheduled_program, env.defrag_move_areas())
+                        && env.can_issue(&scheduled_program)
+                    {
+                        state.place(i, schedule, scheduled_program, scheduled_point);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Scheduler for BeamSearchScheduler {
+    fn add_job(&mut self, job: Job) {
+        self.job_list.push(job);
+    }
+
+    fn run(&mut self, env: &Environment) -> Vec<(JobID, Schedule)> {
+        let est_scheduling_cost = self.latency_predictor.predict();
+
+        self.timer.start();
+        // The time limit is always in the wall-clock time.
+        let start = Instant::now();
+
+        let scheduled_point = env.global_pc() + est_scheduling_cost;
+
+        let jobs = self.job_list.take_batch();
+        let beam_width = self
+            .config
+            .scheduler
+            .beam_width
+            .unwrap_or(DEFAULT_BEAM_WIDTH)
+            .max(1) as usize;
+        let time_limit = self
+            .config
+            .scheduler
+            .time_limit
+            .map(|sec| Duration::from_secs(sec.into()));
+
+        let mut beam = vec![BeamState {
+            schedules: vec![None; jobs.len()],
+            placed: Vec::new(),
+            location_candidates: collect_location_candidates(env, scheduled_point),
+            cost: 0,
+        }];
+        for _ in 0..jobs.len() {
+            let width = if time_limit.is_some_and(|limit| start.elapsed() >= limit) {
+                1
+            } else {
+                beam_width
+            };
+            let children: Vec<_> = beam
+                .iter()
+                .flat_map(|state| expand(env, scheduled_point, &jobs, state))
+                .collect();
+            self.timer.add_work(children.len() as u64);
+            let mut next = select_states(children, width);
+            if next.is_empty() {
+                let mut state = beam.swap_remove(0);
+                place_above(&self.config, env, scheduled_point, &jobs, &mut state);
+                next.push(state);
+            }
+            beam = next;
+        }
+
+        let best = beam.swap_remove(0);
+        tracing::debug!(
+            "Beam search: cost = {}, beam width = {}",
+            best.cost,
+            beam_width
+        );
+        let res = jobs
+            .iter()
+            .map(|job| job.id)
+            .zip(best.schedules.into_iter().map(Option::unwrap))
+            .collect();
+
+        let elapsed = self.timer.stop(jobs.len());
+        self.latency_predictor.observe(elapsed);
+        self.job_list.observe_latency(elapsed, est_scheduling_cost);
+
+        res
+    }
+
+    fn latency_rounds(&self) -> Option<&[LatencyRound]> {
+        Some(self.latency_predictor.rounds())
+    }
+
+    fn scheduling_cycles(&self) -> Option<u64> {
+        self.timer.last_cycles()
+    }
+
+    fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        self.job_list.batch_rounds()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expand, place_above, select_states, BeamSearchScheduler, BeamState};
+    use crate::config::SimulationConfig;
+    use crate::environment::Environment;
+    use crate::job::Job;
+    use crate::program::{Coordinate, Cuboid, Program, ProgramFormat};
+    use crate::scheduler::{apply_schedule, placement_cost, Scheduler};
+    use crate::test_utils;
+
+    #[test]
+    fn test_beam_search_chooses_job_order() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.size_x = 2;
+        config.size_y = 2;
+        config.scheduler.batch_size = None;
+        config.scheduler.time_limit = None;
+        config.scheduler.beam_width = Some(4);
+        let env = Environment::new(config.clone());
+
+        let cuboid = |sx, sy, sz| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                sx,
+                sy,
+                sz,
+            )]))
+        };
+        let programs = [cuboid(1, 2, 1), cuboid(2, 2, 3), cuboid(1, 2, 3)];
+        let mut scheduler = BeamSearchScheduler::new(config);
+        for (i, program) in programs.iter().enumerate() {
+            scheduler.add_job(Job::new(i as u32, 0, program.clone()));
+        }
+        let schedules = scheduler.run(&env);
+
+        // The 1x2 programs are placed side by side, and then the 2x2 one is placed on them.
+        // (The corner greedy algorithm gives the cost 3 * 7 + (1 + 4 + 7) = 33.)
+        let placed: Vec<_> = schedules
+            .iter()
+            .map(|(id, s)| apply_schedule(&programs[*id as usize], s))
+            .collect();
+        assert_eq!(placement_cost(&placed, 0), 3 * 6 + (1 + 3 + 6));
+    }
+
+    #[test]
+    fn test_beam_search_skips_same_placements() {
+        let config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let env = Environment::new(config);
+        let unit = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(0, 0, 0),
+            1,
+            1,
+            1,
+        )]));
+        let jobs = vec![Job::new(0, 0, unit.clone()), Job::new(1, 0, unit)];
+        let root = BeamState {
+            schedules: vec![None; 2],
+            placed: Vec::new(),
+            location_candidates: vec![Coordinate::new(0, 0, 0)],
+            cost: 0,
+        };
+
+        // Placing either job with either rotation at the origin is the same placement.
+        let children = expand(&env, 0, &jobs, &root);
+        assert_eq!(children.len(), 4);
+        let beam = select_states(children, 8);
+        assert_eq!(beam.len(), 1);
+
+        // The other job is placed at one of the three corners of the first one.
+        let beam = select_states(expand(&env, 0, &jobs, &beam[0]), 8);
+        assert_eq!(beam.len(), 3);
+        for (i, s1) in beam.iter().enumerate() {
+            assert!(beam[i + 1..].iter().all(|s2| !s1.is_same_placement(s2)));
+        }
+    }
+
+    #[test]
+    fn test_beam_search_places_above_without_candidates() {
+        let config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let mut env = Environment::new(config.clone());
+        let cuboid = |x, sz| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(x, 0, 0),
+                1,
+                6,
+                sz,
+            )]))
+        };
+        assert!(env.issue_program(&cuboid(0, 5)));
+        let jobs = vec![Job::new(0, 0, cuboid(0, 2)), Job::new(1, 0, cuboid(0, 1))];
+        let mut state = BeamState {
+            schedules: vec![None, Some(super::Schedule::new(1, 0, 0, 0, false))],
+            placed: vec![cuboid(1, 7)],
+            location_candidates: Vec::new(),
+            cost: 0,
+        };
+        assert!(expand(&env, 0, &jobs, &state).is_empty());
+
+        // The job is placed at the top of the running and placed programs.
+        place_above(&config, &env, 0, &jobs, &mut state);
+        let schedule = state.schedules[0].clone().unwrap();
+        assert_eq!((schedule.x, schedule.y, schedule.z), (0, 0, 7));
+        assert!(env.can_issue(&apply_schedule(&jobs[0].program, &schedule)));
+        assert_eq!(state.placed.len(), 2);
+    }
+}
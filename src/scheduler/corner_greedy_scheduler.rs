@@ -1,178 +1,527 @@
 use crate::config::SimulationConfig;
 use crate::environment::Environment;
 use crate::job::Job;
-use crate::program::{is_overlap, Coordinate, Program, ProgramFormat};
-use crate::scheduler::{apply_schedule, JobID, Schedule, Scheduler};
+use crate::program::{Coordinate, Cuboid, Program, ProgramFormat, SpatialIndex};
+use crate::scheduler::parallel::CandidateEvaluator;
+use crate::scheduler::{
+    apply_schedule, BatchRound, JobID, JobQueue, LatencyPredictor, LatencyRound, Schedule,
+    Scheduler, SchedulingTimer,
+};
 
-use std::collections::{HashSet, VecDeque};
-use std::time::Instant;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 pub struct CornerGreedyScheduler {
-    job_list: VecDeque<Job>,
-    config: SimulationConfig,
-    schedule_cycles_sum: u64,
-    schedule_count: u64,
+    job_list: JobQueue,
+    latency_predictor: LatencyPredictor,
+    timer: SchedulingTimer,
+    evaluator: CandidateEvaluator,
+    location_candidates: LocationCandidates,
 }
 
 impl CornerGreedyScheduler {
     pub fn new(config: SimulationConfig) -> Self {
         Self {
-            job_list: VecDeque::new(),
-            config,
-            schedule_cycles_sum: 0,
-            schedule_count: 0,
+            job_list: JobQueue::new(&config.scheduler),
+            latency_predictor: LatencyPredictor::new(config.scheduler.latency_predictor.clone()),
+            timer: SchedulingTimer::new(&config),
+            evaluator: CandidateEvaluator::new(config.scheduler.num_threads),
+            location_candidates: LocationCandidates::new(&config),
         }
     }
+}
 
-    fn take_jobs_by_batch_size(&mut self) -> Vec<Job> {
-        let take_len = if let Some(batch_size) = self.config.scheduler.batch_size {
-            usize::min(self.job_list.len(), batch_size as usize)
-        } else {
-            self.job_list.len()
-        };
-        let mut taken_jobs = self.job_list.split_off(take_len);
-        std::mem::swap(&mut taken_jobs, &mut self.job_list);
-        taken_jobs.into()
-    }
-}
-
-fn create_location_candidate(p: &Program) -> Vec<Coordinate> {
-    let (x1, x2, y1, y2, z1, z2) = match p.format() {
-        ProgramFormat::Polycube(p) => (
-            p.min_x(),
-            p.max_x(),
-            p.min_y(),
-            p.max_y(),
-            p.min_z(),
-            p.max_z(),
-        ),
-        ProgramFormat::Cuboid(cs) => cs.iter().fold(
-            (i32::MAX, i32::MIN, i32::MAX, i32::MIN, i32::MAX, i32::MIN),
-            |(x1, x2, y1, y2, z1, z2), c| {
-                (
-                    i32::min(x1, c.pos().x),
-                    i32::max(x2, c.pos().x + (c.size_x() as i32)),
-                    i32::min(y1, c.pos().y),
-                    i32::max(y2, c.pos().y + (c.size_y() as i32)),
-                    i32::min(z1, c.pos().z),
-                    i32::max(z2, c.pos().z + (c.size_z() as i32)),
-                )
-            },
-        ),
+/// Returns the corners of the cuboid where other programs can be placed. `z1` is used as the
+/// bottom of the cuboid.
+fn cuboid_corners(c: &Cuboid, z1: i32) -> [Coordinate; 4] {
+    [
+        Coordinate::new(c.x2(), c.y1(), z1),
+        Coordinate::new(c.x1(), c.y2(), z1),
+        Coordinate::new(c.x1(), c.y1(), c.z2()),
+        Coordinate::new(0, 0, c.z2()),
+    ]
+}
+
+/// Returns location candidates created by the program. For k-cuboid programs, the corners of
+/// every cuboid are used.
+pub(crate) fn create_location_candidate(p: &Program) -> Vec<Coordinate> {
+    match p.format() {
+        ProgramFormat::Polycube(_) => {
+            let bbox = p.bounding_box();
+            cuboid_corners(&bbox, bbox.z1()).to_vec()
+        }
+        ProgramFormat::Cuboid(cs) => cs.iter().flat_map(|c| cuboid_corners(c, c.z1())).collect(),
+    }
+}
+
+/// Returns the cuboids occupied by the program. A polycube program is approximated by its
+/// bounding box.
+fn covering_cuboids(p: &Program) -> Vec<Cuboid> {
+    match p.format() {
+        ProgramFormat::Polycube(_) => vec![p.bounding_box()],
+        ProgramFormat::Cuboid(cs) => cs.clone(),
+    }
+}
+
+/// Collects location candidates from the corners of running programs.
+pub(crate) fn collect_location_candidates(
+    env: &Environment,
+    scheduled_point: u64,
+) -> Vec<Coordinate> {
+    let mut already_used: HashSet<_> = env.running_programs().iter().map(|p| p.pos()).collect();
+
+    let mut location_candidates: Vec<_> = env
+        .running_programs()
+        .iter()
+        .flat_map(covering_cuboids)
+        .filter(|c| c.z2() as u64 > scheduled_point)
+        .flat_map(|c| cuboid_corners(&c, c.z1().max(scheduled_point as i32)))
+        .filter(|pos| already_used.insert(pos.clone())) // also removes duplicates
+        .collect();
+    if location_candidates.is_empty() {
+        location_candidates.push(Coordinate::new(0, 0, scheduled_point as i32));
+    }
+    location_candidates
+}
+
+/// A set of keys each alive until its expiry, i.e., while the global PC is below it.
+#[derive(Debug)]
+struct ExpiringSet<K> {
+    /// key -> expiry
+    items: BTreeMap<K, i32>,
+    /// (expiry, key) to drop expired keys
+    ends: BTreeSet<(i32, K)>,
+}
+
+impl<K> Default for ExpiringSet<K> {
+    fn default() -> Self {
+        Self {
+            items: BTreeMap::new(),
+            ends: BTreeSet::new(),
+        }
+    }
+}
+
+impl<K: Ord + Clone> ExpiringSet<K> {
+    /// Adds the key, or extends its expiry if it is already in the set.
+    fn insert(&mut self, key: K, expiry: i32) {
+        let current = self.items.entry(key.clone()).or_insert(expiry);
+        if *current < expiry {
+            self.ends.remove(&(*current, key.clone()));
+            *current = expiry;
+        }
+        self.ends.insert((*current, key));
+    }
+
+    fn remove(&mut self, key: &K) -> Option<i32> {
+        let expiry = self.items.remove(key)?;
+        self.ends.remove(&(expiry, key.clone()));
+        Some(expiry)
+    }
+
+    fn get(&self, key: &K) -> Option<i32> {
+        self.items.get(key).copied()
+    }
+
+    /// Drops the keys expiring at or below `pc`.
+    fn expire(&mut self, pc: i32) {
+        while let Some((expiry, key)) = self.ends.first().cloned() {
+            if expiry > pc {
+                break;
+            }
+            self.ends.pop_first();
+            self.items.remove(&key);
+        }
+    }
+}
+
+/// The `z` of the corners below the global PC. Since they are raised to the scheduled point in
+/// every later scheduling, the ones at the same (x, y) are merged.
+const FLOOR: i32 = i32::MIN;
+
+/// The location candidates of `collect_location_candidates` maintained across schedulings.
+///
+/// The corners of a program are added when it is issued and dropped when it finishes, so each
+/// scheduling only visits the live corners instead of all running programs. Corners are pruned
+/// when no program can be placed there during their lifetime:
+/// - corners outside the chip,
+/// - corners at the position of a program running at least as long as them, and
+/// - corners below the global PC except the longest-lived one at each (x, y), which covers the
+///   others because they all are raised to the scheduled point.
+///
+/// So the corners below the global PC are at most one per (x, y) of the chip however many
+/// programs are running. Since a defragmentation moves programs, everything is rebuilt after it.
+#[derive(Debug, Default)]
+pub(crate) struct LocationCandidates {
+    size_x: i32,
+    size_y: i32,
+    /// (z, x, y) -> the largest z2 of the cuboids with the corner
+    corners: ExpiringSet<(i32, i32, i32)>,
+    /// (z, x, y) -> the largest z2 of the running programs at the position
+    positions: ExpiringSet<(i32, i32, i32)>,
+    /// The global PC up to which the corners are merged
+    floor_pc: i32,
+    /// The number of programs in `env.issued_programs()` already added
+    num_issued: usize,
+    defrag_count: u64,
+}
+
+fn key(pos: &Coordinate) -> (i32, i32, i32) {
+    (pos.z, pos.x, pos.y)
+}
+
+impl LocationCandidates {
+    pub(crate) fn new(config: &SimulationConfig) -> Self {
+        Self {
+            size_x: config.size_x as i32,
+            size_y: config.size_y as i32,
+            floor_pc: FLOOR,
+            ..Self::default()
+        }
+    }
+
+    fn add_program(&mut self, program: &Program) {
+        let pos = key(&program.pos());
+        if self.corners.get(&pos).is_some_and(|z2| z2 <= program.z2()) {
+            self.corners.remove(&pos);
+        }
+        self.positions.insert(pos, program.z2());
+
+        for c in covering_cuboids(program) {
+            for corner in cuboid_corners(&c, c.z1()) {
+                if corner.x >= self.size_x || corner.y >= self.size_y {
+                    continue;
+                }
+                let mut corner = key(&corner);
+                if self.positions.get(&corner).is_some_and(|z2| z2 >= c.z2()) {
+                    continue;
+                }
+                if corner.0 <= self.floor_pc {
+                    corner.0 = FLOOR;
+                }
+                self.corners.insert(corner, c.z2());
+            }
+        }
+    }
+
+    /// Reflects the programs issued, finished or moved since the last update.
+    fn update(&mut self, env: &Environment) {
+        if self.defrag_count != env.defrag_count() {
+            *self = Self {
+                size_x: self.size_x,
+                size_y: self.size_y,
+                floor_pc: FLOOR,
+                num_issued: env.issued_programs().len(),
+                defrag_count: env.defrag_count(),
+                ..Self::default()
+            };
+            for program in env.running_programs().iter() {
+                self.add_program(program);
+            }
+        }
+
+        for program in &env.issued_programs()[self.num_issued..] {
+            self.add_program(program);
+        }
+        self.num_issued = env.issued_programs().len();
+
+        // Programs with z2 <= global PC are finished.
+        let global_pc = env.global_pc() as i32;
+        self.corners.expire(global_pc);
+        self.positions.expire(global_pc);
+
+        // Merge the corners which have fallen below the global PC.
+        if self.floor_pc < global_pc {
+            let below: Vec<_> = self
+                .corners
+                .items
+                .range((FLOOR + 1, i32::MIN, i32::MIN)..(global_pc + 1, i32::MIN, i32::MIN))
+                .map(|(corner, _)| *corner)
+                .collect();
+            for corner in below {
+                let z2 = self.corners.remove(&corner).unwrap();
+                self.corners.insert((FLOOR, corner.1, corner.2), z2);
+            }
+            self.floor_pc = global_pc;
+        }
+    }
+
+    /// Returns the candidates of `collect_location_candidates` except those pruned.
+    pub(crate) fn collect(&mut self, env: &Environment, scheduled_point: u64) -> Vec<Coordinate> {
+        self.update(env);
+
+        let scheduled_point = scheduled_point as i32;
+        let mut already_used = HashSet::new();
+        let mut location_candidates: Vec<_> = self
+            .corners
+            .items
+            .iter()
+            .filter(|(_, &z2)| z2 > scheduled_point)
+            .map(|(&(z, x, y), _)| Coordinate::new(x, y, z.max(scheduled_point)))
+            .filter(|pos| self.positions.get(&key(pos)).is_none())
+            .filter(|pos| already_used.insert(pos.clone())) // also removes duplicates
+            .collect();
+        if location_candidates.is_empty() {
+            location_candidates.push(Coordinate::new(0, 0, scheduled_point));
+        }
+        location_candidates
+    }
+}
+
+/// Returns true if the given program is executed across the area used for the defragmentation
+/// moves. Polycube programs are checked by their bounding boxes.
+pub(crate) fn is_overlap_with_moves(program: &Program, move_areas: &[Cuboid]) -> bool {
+    let cuboids = covering_cuboids(program);
+    move_areas.iter().any(|c1| {
+        assert!(c1.z1() == c1.z2()); // because c1 is dummy cuboid
+        cuboids.iter().any(|c2| {
+            let is_overlap_x = !(c1.x2() <= c2.x1() || c2.x2() <= c1.x1());
+            let is_overlap_y = !(c1.y2() <= c2.y1() || c2.y2() <= c1.y1());
+            let is_overlap_z = c2.z1() < c1.z1() && c1.z1() < c2.z2();
+            is_overlap_x && is_overlap_y && is_overlap_z
+        })
+    })
+}
+
+/// Returns the best location of the program among the location candidates, i.e., the one with
+/// the least (z, x + y), together with the index of the used candidate. The program must not
+/// overlap with the programs in `placed`, running programs and the defragmentation moves.
+pub(crate) fn find_best_location(
+    env: &Environment,
+    evaluator: &CandidateEvaluator,
+    location_candidates: &[Coordinate],
+    placed: &SpatialIndex,
+    program: &Program,
+) -> Option<(usize, Schedule)> {
+    // The k-th candidate is the (k / 2)-th location with the rotation k % 2.
+    let schedule_at = |k: usize| {
+        let candidate = &location_candidates[k / 2];
+        Schedule::new(candidate.x, candidate.y, candidate.z, (k % 2) as i32, false)
     };
+    let (k, _) = evaluator.min_by_key(location_candidates.len() * 2, |k| {
+        let schedule = schedule_at(k);
+        let scheduled_program = apply_schedule(program, &schedule);
+        let is_feasible = !placed.is_overlap(&scheduled_program)
+            && !is_overlap_with_moves(&scheduled_program, env.defrag_move_areas())
+            && env.can_issue(&scheduled_program);
+        is_feasible.then_some((schedule.z, schedule.x + schedule.y))
+    })?;
+    Some((k / 2, schedule_at(k)))
+}
 
-    vec![
-        Coordinate::new(x2, y1, z1),
-        Coordinate::new(x1, y2, z1),
-        Coordinate::new(x1, y1, z2),
-        Coordinate::new(0, 0, z2),
-    ]
+/// Places the given jobs one by one at the best corner of running (or already placed) programs.
+pub(crate) fn place_jobs_at_corners(
+    env: &Environment,
+    evaluator: &CandidateEvaluator,
+    scheduled_point: u64,
+    jobs: &[Job],
+) -> Vec<Schedule> {
+    let location_candidates = collect_location_candidates(env, scheduled_point);
+    place_jobs_at_candidates(env, evaluator, location_candidates, jobs)
+}
+
+/// Places the given jobs one by one at the best location candidate. The used candidate is
+/// replaced with the corners of the placed program.
+fn place_jobs_at_candidates(
+    env: &Environment,
+    evaluator: &CandidateEvaluator,
+    mut location_candidates: Vec<Coordinate>,
+    jobs: &[Job],
+) -> Vec<Schedule> {
+    tracing::debug!(
+        "PC = {},  #(location candidates) = {},  #(defrag_move_areas) = {}",
+        env.global_pc(),
+        location_candidates.len(),
+        env.defrag_move_areas().len()
+    );
+
+    let mut res = Vec::new();
+    let mut scheduled_programs = SpatialIndex::new(); // programs to be issued in this scheduling
+    for job in jobs {
+        let (best_it, best_schedule) = find_best_location(
+            env,
+            evaluator,
+            &location_candidates,
+            &scheduled_programs,
+            &job.program,
+        )
+        .unwrap();
+        let scheduled_program = apply_schedule(&job.program, &best_schedule);
+        location_candidates.remove(best_it);
+        location_candidates.extend(create_location_candidate(&scheduled_program));
+        scheduled_programs.insert(scheduled_program);
+        res.push(best_schedule);
+    }
+
+    res
 }
 
 impl Scheduler for CornerGreedyScheduler {
     fn add_job(&mut self, job: Job) {
-        self.job_list.push_back(job);
+        self.job_list.push(job);
     }
 
     fn run(&mut self, env: &Environment) -> Vec<(JobID, Schedule)> {
-        let est_scheduling_cost = if self.schedule_count == 0 {
-            0
-        } else {
-            self.schedule_cycles_sum / self.schedule_count
-        };
+        let est_scheduling_cost = self.latency_predictor.predict();
 
-        let start = Instant::now();
+        self.timer.start();
 
         let scheduled_point = env.global_pc() + est_scheduling_cost;
 
-        let already_used: HashSet<_> = env.running_programs().iter().map(|p| p.pos()).collect();
+        let jobs = self.job_list.take_batch();
+        let location_candidates = self.location_candidates.collect(env, scheduled_point);
+        let schedules = place_jobs_at_candidates(env, &self.evaluator, location_candidates, &jobs);
+        let res = jobs.iter().map(|job| job.id).zip(schedules).collect();
+
+        self.timer.add_work(self.evaluator.take_evaluated());
+        let elapsed = self.timer.stop(jobs.len());
+        self.latency_predictor.observe(elapsed);
+        self.job_list.observe_latency(elapsed, est_scheduling_cost);
+
+        res
+    }
+
+    fn latency_rounds(&self) -> Option<&[LatencyRound]> {
+        Some(self.latency_predictor.rounds())
+    }
+
+    fn scheduling_cycles(&self) -> Option<u64> {
+        self.timer.last_cycles()
+    }
+
+    fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        self.job_list.batch_rounds()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{collect_location_candidates, place_jobs_at_corners, LocationCandidates};
+    use crate::config::SimulationConfig;
+    use crate::environment::Environment;
+    use crate::job::Job;
+    use crate::program::{is_overlap, Coordinate, Cuboid, Program, ProgramFormat};
+    use crate::scheduler::apply_schedule;
+    use crate::scheduler::parallel::CandidateEvaluator;
+    use crate::test_utils;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_place_k_cuboid_programs() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.size_x = 3;
+        config.size_y = 3;
+        let env = Environment::new(config);
 
-        // TODO: incremental management of location candidates
-        let mut location_candidates: Vec<_> = env
-            .running_programs()
+        // a wide cuboid with a narrow one on it
+        let p1 = Program::new(ProgramFormat::Cuboid(vec![
+            Cuboid::new(Coordinate::new(0, 0, 0), 3, 3, 1),
+            Cuboid::new(Coordinate::new(0, 0, 1), 1, 1, 2),
+        ]));
+        let p2 = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(0, 0, 0),
+            2,
+            3,
+            2,
+        )]));
+        let jobs = vec![Job::new(0, 0, p1), Job::new(1, 0, p2)];
+        let schedules = place_jobs_at_corners(&env, &CandidateEvaluator::sequential(), 0, &jobs);
+
+        // The second program is placed next to the narrow part of the first one.
+        assert_eq!(schedules[0].z, 0);
+        assert_eq!(schedules[1].z, 1);
+        let placed: Vec<_> = jobs
             .iter()
-            .filter(|prog| prog.z2() as u64 > scheduled_point)
-            .flat_map(|prog| {
-                let cuboids = prog.cuboid().unwrap();
-                assert!(cuboids.len() == 1);
-                let x1 = cuboids[0].x1();
-                let x2 = cuboids[0].x2();
-                let y1 = cuboids[0].y1();
-                let y2 = cuboids[0].y2();
-                let z1 = cuboids[0].z1().max(scheduled_point as i32);
-                let z2 = cuboids[0].z2();
-                vec![
-                    Coordinate::new(x2, y1, z1),
-                    Coordinate::new(x1, y2, z1),
-                    Coordinate::new(x1, y1, z2),
-                    Coordinate::new(0, 0, z2),
-                ]
-                .into_iter()
-                .filter(|pos| !already_used.contains(pos))
-                .collect::<Vec<_>>()
-            })
+            .zip(&schedules)
+            .map(|(job, s)| apply_schedule(&job.program, s))
             .collect();
-        if location_candidates.is_empty() {
-            location_candidates.push(Coordinate::new(0, 0, scheduled_point as i32));
-        }
+        assert!(!is_overlap(&placed[0], &placed[1]));
+    }
 
-        let defrag_move_areas = env.defrag_move_areas();
+    #[test]
+    fn test_incremental_location_candidates() {
+        let config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let mut candidates = LocationCandidates::new(&config);
+        let mut env = Environment::new(config);
+        let cuboid = |x, y, z, sx, sy, sz| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(x, y, z),
+                sx,
+                sy,
+                sz,
+            )]))
+        };
+        // The candidates are those of `collect_location_candidates` except the ones occupied by
+        // running programs.
+        let check = |candidates: &mut LocationCandidates, env: &Environment, point: u64| {
+            let expected: HashSet<_> = collect_location_candidates(env, point)
+                .into_iter()
+                .filter(|pos| pos.x < 6 && pos.y < 6)
+                .collect();
+            let actual = candidates.collect(env, point);
+            assert_eq!(actual.iter().collect::<HashSet<_>>().len(), actual.len());
+            assert!(actual.iter().all(|pos| expected.contains(pos)));
+            assert!(expected
+                .iter()
+                .filter(|pos| !actual.contains(pos))
+                .all(|pos| !env.can_issue(&cuboid(pos.x, pos.y, pos.z, 1, 1, 1))));
+        };
 
-        tracing::debug!(
-            "PC = {},  #(location candidates) = {},  #(defrag_move_areas) = {}",
-            env.global_pc(),
-            location_candidates.len(),
-            defrag_move_areas.len()
-        );
+        check(&mut candidates, &env, 0);
+        assert!(env.issue_program(&cuboid(0, 0, 0, 6, 2, 3)));
+        assert!(env.issue_program(&cuboid(3, 2, 0, 3, 4, 2)));
+        check(&mut candidates, &env, 1);
+        // The corner (6, 0, 1) of the first program is outside the chip.
+        assert!(!candidates
+            .collect(&env, 1)
+            .contains(&Coordinate::new(6, 0, 1)));
 
-        let mut res = Vec::new();
-        let mut scheduled_programs = Vec::new(); // programs to be issued in this scheduling
-        let jobs = self.take_jobs_by_batch_size();
-        let cmp_schedule = |s1: &Schedule, s2: &Schedule| (s1.z, s1.x + s1.y) < (s2.z, s2.x + s2.y);
-        for job in jobs {
-            let mut best_it = None;
-            let mut best: Option<Schedule> = None;
-            for (i, candidate) in location_candidates.iter().enumerate() {
-                for rot in 0..2 {
-                    let schedule = Schedule::new(candidate.x, candidate.y, candidate.z, rot, false);
-                    let scheduled_program = apply_schedule(&job.program, &schedule);
-                    let is_overlap = scheduled_programs
-                        .iter()
-                        .any(|p| is_overlap(&scheduled_program, p));
-                    let is_overlap_with_moves = defrag_move_areas.iter().any(|c1| {
-                        assert!(c1.z1() == c1.z2()); // because c1 is dummy cuboid
-                        let c2 = &scheduled_program.cuboid().unwrap()[0];
-                        let is_overlap_x = !(c1.x2() <= c2.x1() || c2.x2() <= c1.x1());
-                        let is_overlap_y = !(c1.y2() <= c2.y1() || c2.y2() <= c1.y1());
-                        let is_overlap_z = c2.z1() < c1.z1() && c1.z1() < c2.z2();
-                        is_overlap_x && is_overlap_y && is_overlap_z
-                    });
-                    if !is_overlap
-                        && !is_overlap_with_moves
-                        && env.can_issue(&scheduled_program)
-                        && (best.is_none() || cmp_schedule(&schedule, best.as_ref().unwrap()))
-                    {
-                        best = Some(schedule);
-                        best_it = Some(i);
-                    }
-                }
-            }
+        env.advance_by(2);
+        assert!(env.issue_program(&cuboid(0, 2, 5, 2, 2, 3)));
+        check(&mut candidates, &env, 2);
+        check(&mut candidates, &env, 4);
 
-            let best_schedule = best.unwrap();
-            let scheduled_program = apply_schedule(&job.program, &best_schedule);
-            location_candidates.remove(best_it.expect(""));
-            location_candidates.extend(create_location_candidate(&scheduled_program));
-            scheduled_programs.push(scheduled_program);
-            res.push((job.id, best_schedule));
-        }
+        // The last program is dropped onto the first one.
+        env.defrag_at(3);
+        check(&mut candidates, &env, 3);
+    }
 
-        let elapsed = start
-            .elapsed()
-            .as_micros()
-            .div_ceil(self.config.micro_sec_per_cycle.into()) as u64;
-        self.schedule_cycles_sum += elapsed;
-        self.schedule_count += 1;
+    #[test]
+    fn test_location_candidates_do_not_scale_with_running_programs() {
+        let config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let mut candidates = LocationCandidates::new(&config);
+        let mut env = Environment::new(config);
+        let cuboid = |x, y, z, sx, sy, sz| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(x, y, z),
+                sx,
+                sy,
+                sz,
+            )]))
+        };
 
-        res
+        // Each layer is covered by a program on the one below, and the other corners are outside
+        // the chip.
+        for z in 0..100 {
+            assert!(env.issue_program(&cuboid(0, 0, z, 6, 6, 1)));
+        }
+        assert_eq!(env.running_programs().len(), 100);
+        assert_eq!(
+            candidates.collect(&env, 0),
+            vec![Coordinate::new(0, 0, 100)]
+        );
+        assert_eq!(candidates.corners.items.len(), 1);
+
+        // Long programs started at different points leave one corner per (x, y) below the PC.
+        for x in 0..6 {
+            assert!(env.issue_program(&cuboid(x, 0, 100 + x, 1, 1, 50)));
+        }
+        env.advance_by(110);
+        assert_eq!(env.running_programs().len(), 6);
+        let actual = candidates.collect(&env, 110);
+        let floor: Vec<_> = actual.iter().filter(|pos| pos.z == 110).collect();
+        // (x + 1, 0) and (x, 1) for each program, and the two top corners except (0, 0, 150)
+        assert_eq!(floor.len(), 5 + 6);
+        assert_eq!(actual.len(), floor.len() + 6 + 5);
+        assert_eq!(candidates.corners.items.len(), actual.len());
     }
 }
@@ -1,21 +1,26 @@
 use crate::config::SimulationConfig;
 use crate::environment::Environment;
 use crate::job::Job;
-use crate::scheduler::{apply_schedule, JobID, Schedule, Scheduler};
-
-use std::collections::VecDeque;
+use crate::program::SpatialIndex;
+use crate::scheduler::corner_greedy_scheduler::is_overlap_with_moves;
+use crate::scheduler::parallel::CandidateEvaluator;
+use crate::scheduler::{
+    apply_schedule, BatchRound, JobID, JobQueue, Schedule, Scheduler, SchedulingTimer,
+};
 
 pub struct GreedyScheduler {
-    job_list: VecDeque<Job>,
-    env: Environment,
+    job_list: JobQueue,
+    evaluator: CandidateEvaluator,
+    timer: SchedulingTimer,
     config: SimulationConfig,
 }
 
 impl GreedyScheduler {
     pub fn new(config: SimulationConfig) -> Self {
         Self {
-            job_list: VecDeque::new(),
-            env: Environment::new(config.clone()),
+            job_list: JobQueue::new(&config.scheduler),
+            evaluator: CandidateEvaluator::new(config.scheduler.num_threads),
+            timer: SchedulingTimer::new(&config),
             config,
         }
     }
@@ -23,46 +28,94 @@ impl GreedyScheduler {
 
 impl Scheduler for GreedyScheduler {
     fn add_job(&mut self, job: Job) {
-        self.job_list.push_back(job);
+        self.job_list.push(job);
     }
 
     fn run(&mut self, env: &Environment) -> Vec<(JobID, Schedule)> {
+        self.timer.start();
+
         let mut res = Vec::new();
-        for job in self.take_jobs_by_batch_size() {
+        let mut scheduled_programs = SpatialIndex::new(); // programs to be issued in this scheduling
+        for job in self.job_list.take_batch() {
+            // The candidates at each z position are ordered by (x, y, flip, rotation).
+            let size_y = self.config.size_y as usize;
+            let num_candidates = self.config.size_x as usize * size_y * 2 * 4;
+            let schedule_at = |dz: u64, k: usize| {
+                let (dx, dy) = (k / (size_y * 8), k / 8 % size_y);
+                let (flip, rot) = (k / 4 % 2 == 1, k % 4);
+                Schedule::new(dx as i32, dy as i32, dz as i32, rot as i32, flip)
+            };
             let mut dz = env.global_pc();
-            'top: loop {
-                for dx in 0..self.config.size_x {
-                    for dy in 0..self.config.size_y {
-                        for f in [0, 1] {
-                            for rot in 0..3 {
-                                let schedule =
-                                    Schedule::new(dx as i32, dy as i32, dz as i32, rot, f == 1);
-                                let program = apply_schedule(&job.program, &schedule);
-                                if self.env.issue_program(&program) {
-                                    res.push((job.id, schedule));
-                                    break 'top;
-                                }
-                            }
-                        }
-                    }
+            loop {
+                let found = self.evaluator.first(num_candidates, |k| {
+                    let program = apply_schedule(&job.program, &schedule_at(dz, k));
+                    env.can_issue(&program)
+                        && !is_overlap_with_moves(&program, env.defrag_move_areas())
+                        && !scheduled_programs.is_overlap(&program)
+                });
+                if let Some(k) = found {
+                    let schedule = schedule_at(dz, k);
+                    scheduled_programs.insert(apply_schedule(&job.program, &schedule));
+                    res.push((job.id, schedule));
+                    break;
                 }
                 dz += 1;
             }
         }
 
+        // The jobs are scheduled at the current PC, so any latency suspends the execution.
+        self.timer.add_work(self.evaluator.take_evaluated());
+        let elapsed = self.timer.stop(res.len());
+        self.job_list.observe_latency(elapsed, 0);
+
         res
     }
+
+    fn scheduling_cycles(&self) -> Option<u64> {
+        self.timer.last_cycles()
+    }
+
+    fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        self.job_list.batch_rounds()
+    }
 }
 
-impl GreedyScheduler {
-    fn take_jobs_by_batch_size(&mut self) -> Vec<Job> {
-        let take_len = if let Some(batch_size) = self.config.scheduler.batch_size {
-            usize::min(self.job_list.len(), batch_size as usize)
-        } else {
-            self.job_list.len()
-        };
-        let mut taken_jobs = self.job_list.split_off(take_len);
-        std::mem::swap(&mut taken_jobs, &mut self.job_list);
-        taken_jobs.into()
+#[cfg(test)]
+mod test {
+    use super::GreedyScheduler;
+    use crate::config::SimulationConfig;
+    use crate::environment::Environment;
+    use crate::job::Job;
+    use crate::program::{Coordinate, Cuboid, Program, ProgramFormat};
+    use crate::scheduler::Scheduler;
+    use crate::test_utils;
+
+    #[test]
+    fn test_greedy_scheduler_uses_env() {
+        let config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let mut env = Environment::new(config.clone());
+        let running = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(0, 0, 0),
+            6,
+            6,
+            2,
+        )]));
+        assert!(env.issue_program(&running));
+
+        let mut scheduler = GreedyScheduler::new(config);
+        for id in 0..2 {
+            let program = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                6,
+                6,
+                1,
+            )]));
+            scheduler.add_job(Job::new(id, 0, program));
+        }
+        let res = scheduler.run(&env);
+
+        // The programs are placed above the running program and do not overlap each other.
+        let zs: Vec<_> = res.iter().map(|(_, s)| s.z).collect();
+        assert_eq!(zs, vec![2, 3]);
     }
 }
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::error::QMPError;
+
+/// The method to predict the latency of the next scheduling from the past ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LatencyPredictorKind {
+    /// The average of all past latencies
+    #[default]
+    Average,
+    /// The exponentially weighted moving average with the smoothing factor `alpha`
+    Ewma,
+    /// The maximum of the last `window` latencies
+    MaxOfLast,
+    /// The `percentile`-th percentile of the last `window` latencies
+    Percentile,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyPredictorConfig {
+    #[serde(default)]
+    pub kind: LatencyPredictorKind,
+    /// The smoothing factor of `ewma` in (0, 1] (0.5 by default)
+    pub alpha: Option<f64>,
+    /// The number of past latencies used by `max-of-last` (10 by default) and `percentile`
+    /// (all by default)
+    pub window: Option<usize>,
+    /// The percentile used by `percentile` in [0, 100] (90 by default)
+    pub percentile: Option<f64>,
+    /// The number of cycles added to the prediction
+    pub safety_margin: Option<u64>,
+}
+
+impl LatencyPredictorConfig {
+    pub fn alpha(&self) -> f64 {
+        self.alpha.unwrap_or(0.5)
+    }
+
+    pub fn percentile(&self) -> f64 {
+        self.percentile.unwrap_or(90.0)
+    }
+
+    /// Checks that `alpha` is in (0, 1] and `percentile` is in [0, 100].
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let alpha = self.alpha();
+        if alpha.is_nan() || alpha <= 0.0 || alpha > 1.0 {
+            let (name, value) = ("alpha", alpha);
+            return Err(QMPError::InvalidLatencyPredictorParam { name, value }.into());
+        }
+        let percentile = self.percentile();
+        if !(0.0..=100.0).contains(&percentile) {
+            let (name, value) = ("percentile", percentile);
+            return Err(QMPError::InvalidLatencyPredictorParam { name, value }.into());
+        }
+        Ok(())
+    }
+}
+
+/// The predicted and actual latency (in cycles) of a scheduling.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyRound {
+    pub predicted: u64,
+    pub actual: u64,
+    /// `actual - predicted`. A positive error means the execution is suspended at the
+    /// scheduling point until the scheduling finishes.
+    pub error: i64,
+}
+
+/// Predicts the latency of the next scheduling, which is used to decide the scheduling point.
+pub struct LatencyPredictor {
+    config: LatencyPredictorConfig,
+    /// The past latencies (only the last `window` ones if the window is set)
+    history: VecDeque<u64>,
+    sum: u64,
+    count: u64,
+    ewma: Option<f64>,
+    prediction: Option<u64>,
+    rounds: Vec<LatencyRound>,
+}
+
+impl LatencyPredictor {
+    pub fn new(config: Option<LatencyPredictorConfig>) -> Self {
+        let mut config = config.unwrap_or_default();
+        if config.kind == LatencyPredictorKind::MaxOfLast {
+            config.window.get_or_insert(10);
+        }
+        // The parameters are validated when the config is loaded.
+        Self {
+            config,
+            history: VecDeque::new(),
+            sum: 0,
+            count: 0,
+            ewma: None,
+            prediction: None,
+            rounds: Vec::new(),
+        }
+    }
+
+    /// Returns the predicted latency of the next scheduling in cycles. The first prediction
+    /// is the safety margin.
+    pub fn predict(&mut self) -> u64 {
+        let estimate = if self.count == 0 {
+            0
+        } else {
+            match self.config.kind {
+                LatencyPredictorKind::Average => self.sum / self.count,
+                LatencyPredictorKind::Ewma => self.ewma.unwrap().ceil() as u64,
+                LatencyPredictorKind::MaxOfLast => *self.history.iter().max().unwrap(),
+                LatencyPredictorKind::Percentile => {
+                    let mut sorted: Vec<_> = self.history.iter().copied().collect();
+                    sorted.sort_unstable();
+                    // nearest-rank method
+                    let p = self.config.percentile() / 100.0;
+                    let rank = (p * sorted.len() as f64).ceil() as usize;
+                    sorted[rank.clamp(1, sorted.len()) - 1]
+                }
+            }
+        };
+        let prediction = estimate + self.config.safety_margin.unwrap_or(0);
+        self.prediction = Some(prediction);
+        prediction
+    }
+
+    /// Records the actual latency of the scheduling predicted by the last `predict`.
+    pub fn observe(&mut self, actual: u64) {
+        let predicted = self
+            .prediction
+            .take()
+            .expect("`predict` must be called before `observe`");
+        self.rounds.push(LatencyRound {
+            predicted,
+            actual,
+            error: actual as i64 - predicted as i64,
+        });
+
+        self.sum += actual;
+        self.count += 1;
+        let alpha = self.config.alpha();
+        self.ewma = Some(match self.ewma {
+            Some(ewma) => alpha * actual as f64 + (1.0 - alpha) * ewma,
+            None => actual as f64,
+        });
+        self.history.push_back(actual);
+        if let Some(window) = self.config.window {
+            while self.history.len() > window.max(1) {
+                self.history.pop_front();
+            }
+        }
+    }
+
+    pub fn rounds(&self) -> &[LatencyRound] {
+        &self.rounds
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LatencyPredictor, LatencyPredictorConfig, LatencyPredictorKind};
+    use crate::config::SimulationConfig;
+    use crate::test_utils;
+
+    fn predictions(config: LatencyPredictorConfig, latencies: &[u64]) -> Vec<u64> {
+        let mut predictor = LatencyPredictor::new(Some(config));
+        latencies
+            .iter()
+            .map(|actual| {
+                let prediction = predictor.predict();
+                predictor.observe(*actual);
+                prediction
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_latency_predictors() {
+        let latencies = [10, 2, 6, 30, 4];
+        let config = |kind| LatencyPredictorConfig {
+            kind,
+            ..Default::default()
+        };
+
+        let average = predictions(config(LatencyPredictorKind::Average), &latencies);
+        assert_eq!(average, vec![0, 10, 6, 6, 12]);
+
+        let ewma = predictions(config(LatencyPredictorKind::Ewma), &latencies);
+        assert_eq!(ewma, vec![0, 10, 6, 6, 18]);
+
+        let max_of_last = LatencyPredictorConfig {
+            window: Some(2),
+            ..config(LatencyPredictorKind::MaxOfLast)
+        };
+        assert_eq!(predictions(max_of_last, &latencies), vec![0, 10, 10, 6, 30]);
+
+        let median = LatencyPredictorConfig {
+            percentile: Some(50.0),
+            safety_margin: Some(1),
+            ..config(LatencyPredictorKind::Percentile)
+        };
+        assert_eq!(predictions(median, &latencies), vec![1, 11, 3, 7, 7]);
+
+        let mut predictor = LatencyPredictor::new(None);
+        predictor.predict();
+        predictor.observe(3);
+        predictor.predict();
+        predictor.observe(1);
+        let errors: Vec<_> = predictor.rounds().iter().map(|r| r.error).collect();
+        assert_eq!(errors, vec![3, -2]);
+    }
+
+    #[test]
+    fn test_validate_latency_predictor() {
+        let config = |alpha, percentile| LatencyPredictorConfig {
+            kind: LatencyPredictorKind::Ewma,
+            alpha,
+            percentile,
+            ..Default::default()
+        };
+        assert!(config(None, None).validate().is_ok());
+        assert!(config(Some(1.0), Some(0.0)).validate().is_ok());
+        for (alpha, percentile) in [(0.0, 50.0), (1.5, 50.0), (f64::NAN, 50.0), (0.5, 100.5)] {
+            assert!(config(Some(alpha), Some(percentile)).validate().is_err());
+        }
+
+        // The config is rejected when it is loaded.
+        let mut sim_config =
+            SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        sim_config.scheduler.latency_predictor = Some(config(Some(-0.1), None));
+        assert!(sim_config.scheduler.validate().is_err());
+    }
+}
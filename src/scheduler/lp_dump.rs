@@ -0,0 +1,518 @@
+use crate::error::QMPError;
+use crate::program::{Coordinate, Cuboid};
+use crate::scheduler::{JobID, Schedule};
+
+use anyhow::Result;
+use good_lp::{Constraint, Expression, IntoAffineExpression, Variable};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Sub;
+use std::path::{Path, PathBuf};
+
+/// The file format of dumped models.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelFormat {
+    /// The CPLEX LP format
+    #[default]
+    Lp,
+    /// The free MPS format
+    Mps,
+}
+
+impl ModelFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ModelFormat::Lp => "lp",
+            ModelFormat::Mps => "mps",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableKind {
+    Continuous,
+    Integer,
+    Binary,
+}
+
+/// What a variable of a packing problem is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelEntity {
+    /// The `cuboid`-th cuboid of the `program`-th program in the batch
+    Cuboid { program: usize, cuboid: usize },
+    /// A fixed cuboid, i.e., a running program or a move area of defragmentation
+    FixedCuboid(usize),
+    /// The `program`-th program in the batch
+    Program(usize),
+    /// A candidate schedule of the `program`-th program in the batch
+    Schedule { program: usize, schedule: Schedule },
+}
+
+#[derive(Debug, Clone)]
+pub struct VariableInfo {
+    pub name: String,
+    pub kind: VariableKind,
+    pub entities: Vec<ModelEntity>,
+}
+
+impl VariableInfo {
+    pub fn new(name: String, kind: VariableKind, entities: Vec<ModelEntity>) -> Self {
+        Self {
+            name,
+            kind,
+            entities,
+        }
+    }
+}
+
+/// Where and how the model of a scheduling round is dumped.
+#[derive(Debug, Clone)]
+pub struct ModelDump {
+    /// The path of the model file without the extension. The sidecar JSON file is written to the
+    /// same path with the extension `json`.
+    pub path: PathBuf,
+    pub format: ModelFormat,
+    /// The job IDs of the programs in the batch
+    pub job_ids: Vec<JobID>,
+    pub fixed_cuboids: Vec<Cuboid>,
+    /// The blocks occupied by running programs in the voxel model of polycube programs
+    pub fixed_blocks: Vec<Coordinate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sense {
+    Le,
+    Eq,
+}
+
+/// `sum_j coef[j] * x[j] (<= | =) rhs`, where `j` is the column index of a variable.
+#[derive(Debug, Clone)]
+struct Row {
+    terms: Vec<(usize, f64)>,
+    sense: Sense,
+    rhs: f64,
+}
+
+/// A constraint `expression (<= | =) 0` created by `linear_constraint!`. Unlike `Constraint`,
+/// the expression is kept so that the constraint can be recorded.
+pub(crate) struct LinearConstraint {
+    expression: Expression,
+    sense: Sense,
+}
+
+impl LinearConstraint {
+    pub fn leq<B, A: Sub<B, Output = Expression>>(a: A, b: B) -> Self {
+        Self {
+            expression: a - b,
+            sense: Sense::Le,
+        }
+    }
+
+    pub fn geq<A, B: Sub<A, Output = Expression>>(a: A, b: B) -> Self {
+        Self {
+            expression: b - a,
+            sense: Sense::Le,
+        }
+    }
+
+    pub fn eq<B, A: Sub<B, Output = Expression>>(a: A, b: B) -> Self {
+        Self {
+            expression: a - b,
+            sense: Sense::Eq,
+        }
+    }
+}
+
+impl From<LinearConstraint> for Constraint {
+    fn from(c: LinearConstraint) -> Self {
+        match c.sense {
+            Sense::Le => good_lp::constraint::leq(c.expression, 0.),
+            Sense::Eq => good_lp::constraint::eq(c.expression, 0.),
+        }
+    }
+}
+
+/// The same as `good_lp::constraint!` except that it creates a `LinearConstraint`.
+macro_rules! linear_constraint {
+    ([$($left:tt)*] <= $($right:tt)*) => {
+        $crate::scheduler::lp_dump::LinearConstraint::leq($($left)*, $($right)*)
+    };
+    ([$($left:tt)*] >= $($right:tt)*) => {
+        $crate::scheduler::lp_dump::LinearConstraint::geq($($left)*, $($right)*)
+    };
+    ([$($left:tt)*] == $($right:tt)*) => {
+        $crate::scheduler::lp_dump::LinearConstraint::eq($($left)*, $($right)*)
+    };
+    ([$($left:tt)*] $next:tt $($right:tt)*) => {
+        $crate::scheduler::lp_dump::linear_constraint!([$($left)* $next] $($right)*)
+    };
+    ($($all:tt)*) => {
+        $crate::scheduler::lp_dump::linear_constraint!([] $($all)*)
+    };
+}
+pub(crate) use linear_constraint;
+
+/// Records the model given to the solver and writes it to files.
+pub(crate) struct ModelRecorder {
+    dump: ModelDump,
+    variables: Vec<VariableInfo>,
+    columns: HashMap<Variable, usize>,
+    objective: Vec<(usize, f64)>,
+    rows: Vec<Row>,
+}
+
+impl ModelRecorder {
+    pub fn new(dump: ModelDump, variables: Vec<(Variable, VariableInfo)>) -> Self {
+        let columns = variables
+            .iter()
+            .enumerate()
+            .map(|(j, (var, _))| (*var, j))
+            .collect();
+        Self {
+            dump,
+            variables: variables.into_iter().map(|(_, info)| info).collect(),
+            columns,
+            objective: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Returns the non-zero terms of the expression sorted by the column.
+    fn terms(&self, expression: &Expression) -> Result<Vec<(usize, f64)>> {
+        let mut terms = expression
+            .linear_coefficients()
+            .filter(|(_, coef)| *coef != 0.)
+            .map(|(var, coef)| match self.columns.get(&var) {
+                Some(column) => Ok((*column, coef)),
+                None => Err(QMPError::UnknownModelVariable(var).into()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        terms.sort_by_key(|(column, _)| *column);
+        Ok(terms)
+    }
+
+    /// Records the objective function to be minimized. The constant term is dropped.
+    pub fn set_objective(&mut self, objective: &Expression) -> Result<()> {
+        self.objective = self.terms(objective)?;
+        Ok(())
+    }
+
+    pub fn add_constraint(&mut self, c: &LinearConstraint) -> Result<()> {
+        let terms = self.terms(&c.expression)?;
+        self.rows.push(Row {
+            terms,
+            sense: c.sense,
+            rhs: -c.expression.constant() + 0., // -0 -> 0
+        });
+        Ok(())
+    }
+
+    /// Writes the model and the sidecar JSON file.
+    pub fn write(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.dump.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let path = self.dump.path.with_extension(self.dump.format.extension());
+        let mut w = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        match self.dump.format {
+            ModelFormat::Lp => self.write_lp(&mut w)?,
+            ModelFormat::Mps => self.write_mps(&mut w)?,
+        }
+        w.flush()?;
+        self.write_sidecar(&self.dump.path.with_extension("json"), &path)
+    }
+
+    fn name(&self, column: usize) -> &str {
+        &self.variables[column].name
+    }
+
+    fn write_lp_terms<W: Write>(&self, w: &mut W, terms: &[(usize, f64)]) -> std::io::Result<()> {
+        if terms.is_empty() {
+            // Some readers need a variable in each row, but a model may have no variables.
+            match self.variables.first() {
+                Some(v) => write!(w, " 0 {}", v.name)?,
+                None => write!(w, " 0")?,
+            }
+        }
+        for (k, (column, coef)) in terms.iter().enumerate() {
+            // Long rows are broken into lines because some readers limit the line length.
+            if k > 0 && k % 8 == 0 {
+                write!(w, "\n   ")?;
+            }
+            let sign = if *coef < 0. { "-" } else { "+" };
+            write!(w, " {} {} {}", sign, coef.abs(), self.name(*column))?;
+        }
+        Ok(())
+    }
+
+    fn write_lp<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(
+            w,
+            "\\ Generated by qmp_scheduler (jobs: {:?})",
+            self.dump.job_ids
+        )?;
+        writeln!(w, "Minimize")?;
+        write!(w, " obj:")?;
+        self.write_lp_terms(w, &self.objective)?;
+        writeln!(w)?;
+        writeln!(w, "Subject To")?;
+        for (i, row) in self.rows.iter().enumerate() {
+            write!(w, " c{}:", i)?;
+            self.write_lp_terms(w, &row.terms)?;
+            let sense = match row.sense {
+                Sense::Le => "<=",
+                Sense::Eq => "=",
+            };
+            writeln!(w, " {} {}", sense, row.rhs)?;
+        }
+        // Variables of good_lp are unbounded unless they are binary.
+        writeln!(w, "Bounds")?;
+        for v in self
+            .variables
+            .iter()
+            .filter(|v| v.kind != VariableKind::Binary)
+        {
+            writeln!(w, " {} free", v.name)?;
+        }
+        for (section, kind) in [
+            ("General", VariableKind::Integer),
+            ("Binary", VariableKind::Binary),
+        ] {
+            writeln!(w, "{}", section)?;
+            for v in self.variables.iter().filter(|v| v.kind == kind) {
+                writeln!(w, " {}", v.name)?;
+            }
+        }
+        writeln!(w, "End")
+    }
+
+    fn write_mps<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let mut column_entries = vec![Vec::new(); self.variables.len()];
+        for (column, coef) in &self.objective {
+            column_entries[*column].push(("obj".to_string(), *coef));
+        }
+        for (i, row) in self.rows.iter().enumerate() {
+            for (column, coef) in &row.terms {
+                column_entries[*column].push((format!("c{}", i), *coef));
+            }
+        }
+
+        writeln!(w, "NAME qmp_scheduler")?;
+        writeln!(w, "ROWS")?;
+        writeln!(w, " N obj")?;
+        for (i, row) in self.rows.iter().enumerate() {
+            let sense = match row.sense {
+                Sense::Le => "L",
+                Sense::Eq => "E",
+            };
+            writeln!(w, " {} c{}", sense, i)?;
+        }
+        writeln!(w, "COLUMNS")?;
+        let mut is_integer_section = false;
+        for (v, entries) in self.variables.iter().zip(&column_entries) {
+            let is_integer = v.kind != VariableKind::Continuous;
+            if is_integer != is_integer_section {
+                let marker = if is_integer { "INTORG" } else { "INTEND" };
+                writeln!(w, " MARKER 'MARKER' '{}'", marker)?;
+                is_integer_section = is_integer;
+            }
+            if entries.is_empty() {
+                // MPS requires at least one entry for each column.
+                writeln!(w, " {} obj 0", v.name)?;
+            }
+            for (row, coef) in entries {
+                writeln!(w, " {} {} {}", v.name, row, coef)?;
+            }
+        }
+        if is_integer_section {
+            writeln!(w, " MARKER 'MARKER' 'INTEND'")?;
+        }
+        writeln!(w, "RHS")?;
+        for (i, row) in self.rows.iter().enumerate() {
+            if row.rhs != 0. {
+                writeln!(w, " RHS c{} {}", i, row.rhs)?;
+            }
+        }
+        writeln!(w, "BOUNDS")?;
+        for v in &self.variables {
+            match v.kind {
+                VariableKind::Binary => writeln!(w, " BV BND {}", v.name)?,
+                _ => writeln!(w, " FR BND {}", v.name)?,
+            }
+        }
+        writeln!(w, "ENDATA")
+    }
+
+    fn write_sidecar(&self, path: &Path, model_path: &Path) -> std::io::Result<()> {
+        let job_ids = &self.dump.job_ids;
+        let entity = |e: &ModelEntity| match e {
+            ModelEntity::Cuboid { program, cuboid } => serde_json::json!({
+                "job_id": job_ids[*program],
+                "cuboid_index": cuboid,
+            }),
+            ModelEntity::FixedCuboid(i) => serde_json::json!({ "fixed_cuboid_index": i }),
+            ModelEntity::Program(program) => serde_json::json!({ "job_id": job_ids[*program] }),
+            ModelEntity::Schedule { program, schedule } => serde_json::json!({
+                "job_id": job_ids[*program],
+                "schedule": schedule,
+            }),
+        };
+        let variables: Vec<_> = self
+            .variables
+            .iter()
+            .map(|v| {
+                serde_json::json!({
+                    "name": v.name,
+                    "kind": v.kind,
+                    "refers_to": v.entities.iter().map(entity).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        let sidecar = serde_json::json!({
+            "model": model_path.file_name().map(|s| s.to_string_lossy()),
+            "job_ids": job_ids,
+            "fixed_cuboids": self.dump.fixed_cuboids,
+            "fixed_blocks": self.dump.fixed_blocks,
+            "num_constraints": self.rows.len(),
+            "variables": variables,
+        });
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, &sidecar)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        LinearConstraint, ModelDump, ModelEntity, ModelFormat, ModelRecorder, VariableInfo,
+        VariableKind,
+    };
+    use crate::program::Coordinate;
+    use good_lp::{variable, variables, Expression};
+
+    #[test]
+    fn test_dump_lp_and_mps() {
+        let mut vars = variables!();
+        let x = vars.add(variable().integer());
+        let a = vars.add(variable().binary());
+        let infos = vec![
+            (
+                x,
+                VariableInfo::new(
+                    "x_0".to_string(),
+                    VariableKind::Integer,
+                    vec![ModelEntity::Cuboid {
+                        program: 0,
+                        cuboid: 0,
+                    }],
+                ),
+            ),
+            (
+                a,
+                VariableInfo::new(
+                    "a_0_1".to_string(),
+                    VariableKind::Binary,
+                    vec![
+                        ModelEntity::Cuboid {
+                            program: 0,
+                            cuboid: 0,
+                        },
+                        ModelEntity::FixedCuboid(0),
+                    ],
+                ),
+            ),
+        ];
+
+        let dir = std::env::temp_dir().join(format!("qmp_lp_dump_{}", std::process::id()));
+        for format in [ModelFormat::Lp, ModelFormat::Mps] {
+            let dump = ModelDump {
+                path: dir.join("round"),
+                format,
+                job_ids: vec![42],
+                fixed_cuboids: vec![],
+                fixed_blocks: vec![Coordinate::new(0, 1, 2)],
+            };
+            let mut recorder = ModelRecorder::new(dump, infos.clone());
+            recorder.set_objective(&Expression::from(x)).unwrap();
+            recorder
+                .add_constraint(&linear_constraint!(x - 3 * a <= 2))
+                .unwrap();
+            recorder
+                .add_constraint(&linear_constraint!(x == 1))
+                .unwrap();
+            recorder.write().unwrap();
+
+            let model = std::fs::read_to_string(dir.join("round").with_extension(match format {
+                ModelFormat::Lp => "lp",
+                ModelFormat::Mps => "mps",
+            }))
+            .unwrap();
+            match format {
+                ModelFormat::Lp => {
+                    assert!(model.contains(" obj: + 1 x_0\n"));
+                    assert!(model.contains(" c0: + 1 x_0 - 3 a_0_1 <= 2\n"));
+                    assert!(model.contains(" c1: + 1 x_0 = 1\n"));
+                    assert!(model.contains("Binary\n a_0_1\nEnd"));
+                }
+                ModelFormat::Mps => {
+                    assert!(model.contains(" L c0\n E c1\n"));
+                    assert!(model.contains(" a_0_1 c0 -3\n"));
+                    assert!(model.contains(" RHS c0 2\n RHS c1 1\n"));
+                    assert!(model.contains(" BV BND a_0_1\n"));
+                }
+            }
+
+            let sidecar: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(dir.join("round.json")).unwrap())
+                    .unwrap();
+            assert_eq!(sidecar["variables"][1]["name"], "a_0_1");
+            assert_eq!(sidecar["variables"][1]["refers_to"][0]["job_id"], 42);
+            assert_eq!(
+                sidecar["variables"][1]["refers_to"][1]["fixed_cuboid_index"],
+                0
+            );
+            assert_eq!(sidecar["fixed_blocks"][0], serde_json::json!([0, 1, 2]));
+        }
+
+        // A variable unknown to the recorder is an error rather than a panic.
+        let mut other_vars = variables!();
+        let y = other_vars.add_vector(variable().integer(), 3)[2];
+        let dump = ModelDump {
+            path: dir.join("round"),
+            format: ModelFormat::Lp,
+            job_ids: vec![42],
+            fixed_cuboids: vec![],
+            fixed_blocks: vec![],
+        };
+        let mut recorder = ModelRecorder::new(dump, infos);
+        assert!(recorder
+            .add_constraint(&linear_constraint!(x + y <= 2))
+            .is_err());
+
+        // Empty rows are written even if the model has no variables.
+        for format in [ModelFormat::Lp, ModelFormat::Mps] {
+            let dump = ModelDump {
+                path: dir.join("empty"),
+                format,
+                job_ids: vec![],
+                fixed_cuboids: vec![],
+                fixed_blocks: vec![],
+            };
+            let mut recorder = ModelRecorder::new(dump, vec![]);
+            recorder.set_objective(&Expression::from(0.)).unwrap();
+            recorder
+                .add_constraint(&LinearConstraint::leq(Expression::from(0.), 1.))
+                .unwrap();
+            recorder.write().unwrap();
+        }
+        let model = std::fs::read_to_string(dir.join("empty.lp")).unwrap();
+        assert!(model.contains(" obj: 0\n"));
+        assert!(model.contains(" c0: 0 <= 1\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
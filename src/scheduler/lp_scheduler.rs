@@ -1,85 +1,259 @@
-use std::time::Instant;
-
 use crate::config::SimulationConfig;
 use crate::environment::Environment;
 use crate::job::Job;
-use crate::program::{Coordinate, Cuboid, Program, ProgramFormat};
-use crate::scheduler::{apply_schedule, JobID, Schedule, Scheduler};
+use crate::program::{Coordinate, Cuboid, Polycube, Program, ProgramFormat, Voxels};
+use crate::scheduler::corner_greedy_scheduler::place_jobs_at_corners;
+use crate::scheduler::lp_dump::{
+    linear_constraint, LinearConstraint, ModelDump, ModelEntity, ModelRecorder, VariableInfo,
+    VariableKind,
+};
+use crate::scheduler::parallel::CandidateEvaluator;
+use crate::scheduler::{
+    apply_schedule, BatchRound, JobID, JobQueue, LatencyPredictor, LatencyRound, Schedule,
+    Scheduler, SchedulingTimer,
+};
 
-#[cfg(not(feature = "with-cplex"))]
+#[cfg(feature = "cbc")]
 use good_lp::solvers::coin_cbc::CoinCbcProblem;
 #[cfg(feature = "with-cplex")]
 use good_lp::solvers::cplex::CPLEXProblem;
+#[cfg(feature = "highs")]
+use good_lp::solvers::highs::HighsProblem;
+use good_lp::solvers::minilp::MiniLpProblem;
+use good_lp::solvers::ResolutionError;
+#[cfg(feature = "cbc")]
+use good_lp::solvers::WithInitialSolution;
 use good_lp::variable::UnsolvedProblem;
-use good_lp::{
-    constraint, variable, variables, Expression, ProblemVariables, Solution, SolverModel, Variable,
-};
+use good_lp::{variable, variables, Expression, ProblemVariables, Solution, SolverModel, Variable};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+
+/// The solver backends of the LP scheduler. Backends other than `minilp` are enabled by the
+/// cargo features of the same names (`with-cplex` for CPLEX).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LPSolver {
+    Cbc,
+    Cplex,
+    Highs,
+    /// A pure-Rust solver, which is always available but ignores `time_limit`
+    Minilp,
+}
 
-use std::collections::{HashMap, VecDeque};
+impl Default for LPSolver {
+    fn default() -> Self {
+        if cfg!(feature = "with-cplex") {
+            LPSolver::Cplex
+        } else if cfg!(feature = "cbc") {
+            LPSolver::Cbc
+        } else {
+            LPSolver::Minilp
+        }
+    }
+}
 
-pub struct LPSolverWrapper {
-    #[cfg(not(feature = "with-cplex"))]
-    problem: CoinCbcProblem,
+impl LPSolver {
+    /// Returns true if the backend is enabled in this build.
+    pub fn is_available(&self) -> bool {
+        match self {
+            LPSolver::Cbc => cfg!(feature = "cbc"),
+            LPSolver::Cplex => cfg!(feature = "with-cplex"),
+            LPSolver::Highs => cfg!(feature = "highs"),
+            LPSolver::Minilp => true,
+        }
+    }
+}
+
+enum SolverProblem {
+    #[cfg(feature = "cbc")]
+    Cbc(CoinCbcProblem),
     #[cfg(feature = "with-cplex")]
-    problem: CPLEXProblem,
+    Cplex(CPLEXProblem),
+    #[cfg(feature = "highs")]
+    Highs(HighsProblem),
+    Minilp(MiniLpProblem),
 }
 
-impl LPSolverWrapper {
-    pub fn new(problem: UnsolvedProblem, time_limit: Option<u32>) -> Self {
-        #[cfg(not(feature = "with-cplex"))]
-        let problem = if let Some(time_limit) = time_limit {
-            let mut problem = problem.using(good_lp::coin_cbc);
-            problem.set_parameter("sec", &format!("{}", time_limit));
-            problem
-        } else {
-            problem.using(good_lp::coin_cbc)
-        };
+impl SolverProblem {
+    #[cfg_attr(
+        not(any(feature = "cbc", feature = "with-cplex", feature = "highs")),
+        allow(unused_variables)
+    )]
+    fn new(problem: UnsolvedProblem, solver: LPSolver, time_limit: Option<u32>) -> Self {
+        match solver {
+            #[cfg(feature = "cbc")]
+            LPSolver::Cbc => {
+                let mut problem = problem.using(good_lp::solvers::coin_cbc::coin_cbc);
+                if let Some(time_limit) = time_limit {
+                    problem.set_parameter("sec", &format!("{}", time_limit));
+                }
+                SolverProblem::Cbc(problem)
+            }
+            #[cfg(feature = "with-cplex")]
+            LPSolver::Cplex => {
+                let problem = if let Some(time_limit) = time_limit {
+                    let sec = std::time::Duration::new(time_limit.into(), 0);
+                    let time_limit = cplex_rs::parameters::TimeLimit(sec);
+                    let mut cplex_env = cplex_rs::Environment::new().expect("");
+                    cplex_env.set_parameter(time_limit).unwrap(); // TODO
+                    good_lp::solvers::cplex::cplex_with_env(problem, cplex_env)
+                } else {
+                    problem.using(good_lp::solvers::cplex::cplex)
+                };
+                SolverProblem::Cplex(problem)
+            }
+            #[cfg(feature = "highs")]
+            LPSolver::Highs => {
+                let mut problem = problem.using(good_lp::solvers::highs::highs);
+                if let Some(time_limit) = time_limit {
+                    problem = problem.set_time_limit(time_limit.into());
+                }
+                SolverProblem::Highs(problem)
+            }
+            LPSolver::Minilp => {
+                SolverProblem::Minilp(problem.using(good_lp::solvers::minilp::minilp))
+            }
+            #[allow(unreachable_patterns)]
+            _ => panic!(
+                "The solver backend {:?} is not enabled in this build",
+                solver
+            ),
+        }
+    }
 
-        #[cfg(feature = "with-cplex")]
-        let problem = {
-            if let Some(time_limit) = time_limit {
-                let sec = std::time::Duration::new(time_limit.into(), 0);
-                let time_limit = cplex_rs::parameters::TimeLimit(sec);
-                let mut cplex_env = cplex_rs::Environment::new().expect("");
-                cplex_env.set_parameter(time_limit).unwrap(); // TODO
-                good_lp::solvers::cplex::cplex_with_env(problem, cplex_env)
-            } else {
-                problem.using(good_lp::solvers::cplex::cplex)
+    fn with(self, c: good_lp::Constraint) -> Self {
+        match self {
+            #[cfg(feature = "cbc")]
+            SolverProblem::Cbc(problem) => SolverProblem::Cbc(problem.with(c)),
+            #[cfg(feature = "with-cplex")]
+            SolverProblem::Cplex(problem) => SolverProblem::Cplex(problem.with(c)),
+            #[cfg(feature = "highs")]
+            SolverProblem::Highs(problem) => SolverProblem::Highs(problem.with(c)),
+            SolverProblem::Minilp(problem) => SolverProblem::Minilp(problem.with(c)),
+        }
+    }
+
+    #[cfg_attr(not(feature = "cbc"), allow(unused_variables))]
+    fn with_initial_solution(self, solution: Vec<(Variable, f64)>) -> Self {
+        match self {
+            #[cfg(feature = "cbc")]
+            SolverProblem::Cbc(problem) => {
+                SolverProblem::Cbc(problem.with_initial_solution(solution))
             }
-        };
+            _ => self,
+        }
+    }
+
+    fn solve(self) -> Result<Box<dyn Solution>, ResolutionError> {
+        Ok(match self {
+            #[cfg(feature = "cbc")]
+            SolverProblem::Cbc(problem) => Box::new(problem.solve()?),
+            #[cfg(feature = "with-cplex")]
+            SolverProblem::Cplex(problem) => Box::new(problem.solve()?),
+            #[cfg(feature = "highs")]
+            SolverProblem::Highs(problem) => Box::new(problem.solve()?),
+            SolverProblem::Minilp(problem) => Box::new(problem.solve()?),
+        })
+    }
+}
+
+pub struct LPSolverWrapper {
+    problem: SolverProblem,
+    recorder: Option<ModelRecorder>,
+}
 
-        Self { problem }
+impl LPSolverWrapper {
+    pub fn new(problem: UnsolvedProblem, solver: LPSolver, time_limit: Option<u32>) -> Self {
+        Self {
+            problem: SolverProblem::new(problem, solver, time_limit),
+            recorder: None,
+        }
     }
 
-    pub fn with(self, c: good_lp::Constraint) -> Self {
+    /// Record the constraints given after this call so that the model is written to files when
+    /// it is solved. The model is not written if it fails to be recorded.
+    pub(crate) fn with_recorder(mut self, recorder: Option<anyhow::Result<ModelRecorder>>) -> Self {
+        self.recorder = recorder.and_then(|recorder| {
+            recorder
+                .map_err(|e| tracing::warn!("Failed to dump the model: {}", e))
+                .ok()
+        });
+        self
+    }
+
+    pub(crate) fn with(mut self, c: LinearConstraint) -> Self {
+        if let Some(Err(e)) = self.recorder.as_mut().map(|r| r.add_constraint(&c)) {
+            tracing::warn!("Failed to dump the model: {}", e);
+            self.recorder = None;
+        }
         Self {
-            problem: self.problem.with(c),
+            problem: self.problem.with(c.into()),
+            recorder: self.recorder,
         }
     }
 
-    #[cfg(not(feature = "with-cplex"))]
-    pub fn solve(
-        self,
-    ) -> Result<<CoinCbcProblem as SolverModel>::Solution, <CoinCbcProblem as SolverModel>::Error>
-    {
-        self.problem.solve()
+    /// Give a (possibly partial) feasible solution to the solver as the initial incumbent. Only
+    /// CBC uses it; the result is still compared with it in `LPScheduler` for other backends.
+    pub fn with_initial_solution(self, solution: Vec<(Variable, f64)>) -> Self {
+        Self {
+            problem: self.problem.with_initial_solution(solution),
+            recorder: self.recorder,
+        }
     }
 
-    #[cfg(feature = "with-cplex")]
-    pub fn solve(
-        self,
-    ) -> Result<<CPLEXProblem as SolverModel>::Solution, <CPLEXProblem as SolverModel>::Error> {
+    pub fn solve(self) -> Result<Box<dyn Solution>, ResolutionError> {
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.write() {
+                tracing::warn!("Failed to dump the model: {}", e);
+            }
+        }
         self.problem.solve()
     }
 }
 
+/// The objective function of the packing problems. `start[i]` and `end[i]` are the start and
+/// completion times of the i-th program measured from the scheduling point, and the weight
+/// `w[i]` is given by the priority of the job.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LPObjective {
+    /// max_i end[i], and then sum_i w[i] * start[i] to break ties. This is implemented as
+    /// max_i end[i] + sum_i w[i] * start[i] / (W * Z + 1), where W is the sum of the weights and
+    /// Z is the maximum z position.
+    #[default]
+    Makespan,
+    /// sum_i (1 + w[i]) * end[i]
+    WeightedCompletion,
+    /// sum_i start[i], which favours short response times
+    SumOfStarts,
+    /// max_i end[i], and then sum_i end[i] to break ties. This is implemented as
+    /// max_i end[i] + sum_i end[i] / (n * Z + 1), where n is the batch size and Z is the maximum
+    /// z position.
+    MakespanThenCompactness,
+}
+
+impl LPObjective {
+    /// The weight of sum_i end[i] that makes the objective lexicographic.
+    fn compactness_weight(num_programs: usize, max_z: u32) -> f64 {
+        1. / (num_programs as f64 * max_z as f64 + 1.)
+    }
+
+    /// The scale of sum_i w[i] * start[i] that keeps the priorities below the makespan.
+    fn priority_weight(weights: &[f64], max_z: u32) -> f64 {
+        1. / (weights.iter().sum::<f64>() * max_z as f64 + 1.)
+    }
+}
+
 #[derive(Debug, Clone)]
 struct PackingConfig {
+    solver: LPSolver,
     time_limit: Option<u32>, // in seconds
     size_x: u32,
     size_y: u32,
     min_z: i32,
     max_z: u32,
+    dump: Option<ModelDump>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -94,20 +268,38 @@ impl ScheduleVarsKey {
     }
 }
 
+#[allow(clippy::large_enum_variant)] // constructed only once per scheduling
 enum PackingProblem {
     Polycube(PolycubePackingProblem),
     Cuboid(CuboidPackingProblem),
+    Mixed(MixedPackingProblem),
 }
 
 impl PackingProblem {
-    pub fn solve(self) -> Vec<Schedule> {
+    fn num_variables(&self) -> usize {
+        match self {
+            PackingProblem::Polycube(problem) => problem.vars.len(),
+            PackingProblem::Cuboid(problem) => problem.vars.len(),
+            PackingProblem::Mixed(problem) => problem.cuboids.vars.len(),
+        }
+    }
+
+    pub fn solve(self) -> Option<Vec<Schedule>> {
         match self {
             PackingProblem::Polycube(problem) => problem.solve(),
             PackingProblem::Cuboid(problem) => problem.solve(),
+            PackingProblem::Mixed(problem) => problem.solve(),
         }
     }
 }
 
+/// The LP problem that chooses one of all possible schedules of each program so that no block is
+/// shared by programs. Cuboid programs are regarded as the sets of their blocks, and thus a batch
+/// may contain both polycube and cuboid programs.
+///
+/// Blocks already occupied (e.g., by running programs) are given as fixed blocks, and schedules
+/// using them are excluded. So are schedules that run across a move area of defragmentation (see
+/// `is_crossing_move_areas`) and schedules outside `min_z..max_z`.
 #[warn(dead_code)]
 struct PolycubePackingProblem {
     config: PackingConfig,
@@ -116,27 +308,58 @@ struct PolycubePackingProblem {
     is_block_present: HashMap<Coordinate, Expression>,
     s_sums: Vec<Expression>,
     total_time: Variable,
+    programs: Vec<Program>,
+    z_offsets: Vec<(i32, i32)>, // the start and end of each program relative to its schedule
+    weights: Vec<f64>,
+    objective: LPObjective,
+    initial_solution: Option<Vec<Schedule>>,
+}
+
+/// Returns true if the program has blocks both before and after the z position of a move area
+/// in the xy range of the area. This is the voxel version of `is_overlap_with_moves`.
+fn is_crossing_move_areas(blocks: &[Coordinate], move_areas: &[Cuboid]) -> bool {
+    move_areas.iter().any(|m| {
+        assert!(m.z1() == m.z2()); // because m is dummy cuboid
+        let in_area =
+            |b: &&Coordinate| m.x1() <= b.x && b.x < m.x2() && m.y1() <= b.y && b.y < m.y2();
+        blocks.iter().filter(in_area).any(|b| b.z < m.z1())
+            && blocks.iter().filter(in_area).any(|b| b.z >= m.z1())
+    })
 }
 
 fn collect_schedule_candidate(
     config: &PackingConfig,
+    fixed_blocks: &Voxels,
+    move_areas: &[Cuboid],
     program: &Program,
 ) -> Vec<(Schedule, Program)> {
     let mut candidates = Vec::new();
-    for r in 0..3 {
-        for f in [0, 1] {
+    // The blocks of the added candidates to skip the orientations giving the same placement,
+    // e.g., rotating a single cuboid by 180 degrees
+    let mut placements = HashSet::new();
+    for r in 0..4 {
+        for f in [false, true] {
             for x in 0..config.size_x {
                 for y in 0..config.size_y {
-                    for z in 0..config.max_z {
-                        let schedule = Schedule::new(x as i32, y as i32, z as i32, r, f == 1);
+                    for z in config.min_z..config.max_z as i32 {
+                        let schedule = Schedule::new(x as i32, y as i32, z, r, f);
                         let scheduled = apply_schedule(program, &schedule);
-                        let poly = scheduled.polycube().unwrap();
-                        if poly.blocks().iter().all(|b| {
+                        let blocks = scheduled.blocks();
+                        let is_in_range = blocks.iter().all(|b| {
                             (b.x as u32) < config.size_x
                                 && (b.y as u32) < config.size_y
+                                && config.min_z <= b.z
                                 && (b.z as u32) < config.max_z
-                        }) {
-                            candidates.push((schedule, scheduled));
+                        });
+                        if is_in_range
+                            && !blocks.iter().any(|b| fixed_blocks.contains(b))
+                            && !is_crossing_move_areas(&blocks, move_areas)
+                        {
+                            let mut key = blocks;
+                            key.sort();
+                            if placements.insert(key) {
+                                candidates.push((schedule, scheduled));
+                            }
                         }
                     }
                 }
@@ -148,31 +371,39 @@ fn collect_schedule_candidate(
 }
 
 impl PolycubePackingProblem {
-    fn new(config: PackingConfig, programs: Vec<Program>) -> Self {
+    fn new(
+        config: PackingConfig,
+        fixed_blocks: Voxels,
+        move_areas: Vec<Cuboid>,
+        programs: Vec<Program>,
+    ) -> Self {
         let mut vars = variables!();
         let mut s_vars = HashMap::new();
         let mut is_block_present = HashMap::new();
         let total_time = vars.add(variable().integer());
         let mut s_sums = Vec::new();
+        let z_offsets = programs
+            .iter()
+            .map(|p| {
+                let zs: Vec<_> = p.blocks().iter().map(|b| b.z).collect();
+                (*zs.iter().min().unwrap(), zs.iter().max().unwrap() + 1)
+            })
+            .collect();
         for (i, program) in programs.iter().enumerate() {
-            let candidates = collect_schedule_candidate(&config, program);
+            let candidates =
+                collect_schedule_candidate(&config, &fixed_blocks, &move_areas, program);
             let mut s_sum: Expression = 0.into();
             for (schedule, scheduled) in candidates {
                 let s_var = vars.add(variable().binary());
                 let s_var_key = ScheduleVarsKey::new(i, schedule);
                 s_vars.insert(s_var_key, s_var);
                 s_sum += s_var;
-                match scheduled.format() {
-                    ProgramFormat::Polycube(p) => {
-                        for block in p.blocks() {
-                            if let Some(exp) = is_block_present.get_mut(block) {
-                                *exp += s_var;
-                            } else {
-                                is_block_present.insert(block.clone(), s_var.into());
-                            }
-                        }
+                for block in scheduled.blocks() {
+                    if let Some(exp) = is_block_present.get_mut(&block) {
+                        *exp += s_var;
+                    } else {
+                        is_block_present.insert(block, s_var.into());
                     }
-                    _ => unimplemented!(),
                 }
             }
             s_sums.push(s_sum);
@@ -185,28 +416,130 @@ impl PolycubePackingProblem {
             s_sums,
             is_block_present,
             total_time,
+            weights: vec![0.; programs.len()],
+            z_offsets,
+            programs,
+            objective: LPObjective::default(),
+            initial_solution: None,
         }
     }
 
-    fn solve(self) -> Vec<Schedule> {
-        let mut problem = self
-            .vars
-            .minimise(self.total_time)
-            .using(good_lp::coin_cbc)
-            .with(constraint!(self.total_time >= 0));
-        if let Some(time_limit) = self.config.time_limit {
-            problem.set_parameter("sec", &format!("{}", time_limit));
+    /// Set the weights of the start times of programs in the objective function.
+    fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        assert_eq!(self.weights.len(), weights.len());
+        self.weights = weights;
+        self
+    }
+
+    fn with_objective(mut self, objective: LPObjective) -> Self {
+        self.objective = objective;
+        self
+    }
+
+    /// Returns the variables with the entities they are about, which are written with the model.
+    fn variables_info(&self) -> Vec<(Variable, VariableInfo)> {
+        let mut keys: Vec<_> = self.s_vars.keys().collect();
+        keys.sort_by_key(|key| {
+            let s = &key.schedule;
+            (key.i, s.z, s.y, s.x, s.rotate, s.flip)
+        });
+        let mut info = Vec::new();
+        let mut count = vec![0; self.programs.len()];
+        for key in keys {
+            let entity = ModelEntity::Schedule {
+                program: key.i,
+                schedule: key.schedule.clone(),
+            };
+            let name = format!("s_{}_{}", key.i, count[key.i]);
+            count[key.i] += 1;
+            info.push((
+                self.s_vars[key],
+                VariableInfo::new(name, VariableKind::Binary, vec![entity]),
+            ));
+        }
+        info.push((
+            self.total_time,
+            VariableInfo::new("total_time".into(), VariableKind::Integer, vec![]),
+        ));
+        info
+    }
+
+    /// Set the schedules used as the initial solution of the solver.
+    fn with_initial_solution(mut self, schedules: Vec<Schedule>) -> Self {
+        assert_eq!(self.weights.len(), schedules.len());
+        self.initial_solution = Some(schedules);
+        self
+    }
+
+    /// Returns None if the solver finds no feasible solution.
+    fn solve(self) -> Option<Vec<Schedule>> {
+        // `total_time` is the maximum z position of blocks, i.e., the makespan minus 1.
+        let compactness_weight =
+            LPObjective::compactness_weight(self.programs.len(), self.config.max_z);
+        let priority_weight = LPObjective::priority_weight(&self.weights, self.config.max_z);
+        let mut objective: Expression = match self.objective {
+            LPObjective::Makespan | LPObjective::MakespanThenCompactness => self.total_time.into(),
+            LPObjective::WeightedCompletion | LPObjective::SumOfStarts => 0.into(),
+        };
+        for (key, s_var) in &self.s_vars {
+            let (start_offset, end_offset) = self.z_offsets[key.i];
+            let start = (key.schedule.z + start_offset) as f64;
+            let end = (key.schedule.z + end_offset) as f64;
+            let coef = match self.objective {
+                LPObjective::Makespan => {
+                    priority_weight * self.weights[key.i] * key.schedule.z as f64
+                }
+                LPObjective::WeightedCompletion => (1. + self.weights[key.i]) * end,
+                LPObjective::SumOfStarts => start,
+                LPObjective::MakespanThenCompactness => compactness_weight * end,
+            };
+            objective += coef * *s_var;
+        }
+        let recorder = self.config.dump.clone().map(|dump| {
+            let mut recorder = ModelRecorder::new(dump, self.variables_info());
+            recorder.set_objective(&objective).map(|_| recorder)
+        });
+        let mut problem = LPSolverWrapper::new(
+            self.vars.minimise(objective),
+            self.config.solver,
+            self.config.time_limit,
+        )
+        .with_recorder(recorder)
+        .with(linear_constraint!(self.total_time >= 0));
+        if let Some(initial) = &self.initial_solution {
+            let total_time = self
+                .programs
+                .iter()
+                .zip(initial)
+                .flat_map(|(p, s)| apply_schedule(p, s).blocks())
+                .map(|b| b.z)
+                .max()
+                .unwrap_or(0);
+            let mut solution: Vec<_> = self
+                .s_vars
+                .iter()
+                .map(|(key, s_var)| {
+                    let value = if initial[key.i] == key.schedule {
+                        1.
+                    } else {
+                        0.
+                    };
+                    (*s_var, value)
+                })
+                .collect();
+            solution.push((self.total_time, total_time as f64));
+            problem = problem.with_initial_solution(solution);
         }
 
         for (pos, is_present) in self.is_block_present {
-            problem = problem.with(constraint!(is_present.clone() <= 1));
-            problem = problem.with(constraint!(self.total_time >= pos.z * is_present));
+            problem = problem.with(linear_constraint!(is_present.clone() <= 1));
+            problem = problem.with(linear_constraint!(self.total_time >= pos.z * is_present));
         }
         for s_sum in self.s_sums {
-            problem = problem.with(constraint!(s_sum == 1));
+            problem = problem.with(linear_constraint!(s_sum == 1));
         }
 
-        let solution = problem.solve().unwrap();
+        let solution = problem.solve().ok()?;
         let mut result = Vec::new();
         for (key, s_var) in self.s_vars {
             if f64::abs(solution.value(s_var) - 1.) <= 1e-8 {
@@ -215,34 +548,48 @@ impl PolycubePackingProblem {
         }
 
         result.sort_by(|(i, _), (j, _)| usize::cmp(i, j));
-        result.into_iter().map(|(_, s)| s).collect()
+        Some(result.into_iter().map(|(_, s)| s).collect())
     }
 }
 
+/// Returns the (x, y) offset of the j-th cuboid of a program from the minimum corner of the
+/// program when it is rotated by 90 degrees. This is consistent with `apply_schedule`.
+fn rotated_offset(cuboids: &[Cuboid], j: usize) -> (i32, i32) {
+    let x1 = cuboids.iter().map(|c| c.x1()).min().unwrap();
+    let y2 = cuboids.iter().map(|c| c.y2()).max().unwrap();
+    // (x, y) -> (-y, x)
+    (y2 - cuboids[j].y2(), cuboids[j].x1() - x1)
+}
+
 /// The specialized LP problem when all programs represented by cuboids.
 /// * Constant values:
 ///   * X, Y                = the chip size (constant value)
 ///   * sx[i], sy[i], sz[i] = the size of i-th cuboid
 ///   * Z                   = sum_i sz[i]
 ///   * dx, dy, dz[i][j]    = relative positions of cuboids (if i, j are in the same program)
+///   * dx', dy'[i][j]      = relative positions of cuboids in the program rotated by 90 degrees
 /// * Variables:
 ///   * a[i][j] := (x pos of i-th cuboid) > (x pos of j-th cuboid) (binary)
 ///   * b[i][j] := (y pos of i-th cuboid) > (y pos of j-th cuboid) (binary)
 ///   * c[i][j] := (z pos of i-th cuboid) > (z pos of j-th cuboid) (binary)
 ///   * x[i], y[i], z[i] := the position of i-th cuboid
-///   * v := the objective value
+///   * r[p] := p-th program is rotated by 90 degrees (binary)
+///   * sx'[i] := sx[i] + (sy[i] - sx[i]) * r[p] (and sy'[i] similarly), where i is in p-th program
+///   * v := the makespan
+/// * Weights:
+///   * w[i] = the weight of the start time of i-th program (0 by default)
 ///
-/// Minimize v
+/// Minimize v + sum_i w[i] * z[i] (or another objective given by `LPObjective`)
 /// s.t.
 ///   * a[i][j] + a[j][i] + b[i][j] + b[j][i] + c[i][j] + c[j][i] >= 1
-///   * x[i] - x[j] + X * a[i][j] <= X - sx[i] for all i, j
-///   * y[i] - y[j] + Y * b[i][j] <= Y - sy[i] for all i, j
+///   * x[i] - x[j] + X * a[i][j] <= X - sx'[i] for all i, j
+///   * y[i] - y[j] + Y * b[i][j] <= Y - sy'[i] for all i, j
 ///   * z[i] - z[j] + Z * c[i][j] <= Z - sz[i] for all i, j
-///   * x[i] + sx[i] <= X
-///   * y[i] + sy[i] <= Y
+///   * x[i] + sx'[i] <= X
+///   * y[i] + sy'[i] <= Y
 ///   * z[i] + sz[i] <= Z
-///   * x[i] + dx[i][j] = x[j] (for all i, j in the same program)
-///   * y[i] + dy[i][j] = y[j] (for all i, j in the same program)
+///   * x[i] + dx[i][j] * (1 - r[p]) + dx'[i][j] * r[p] = x[j] (for all i, j in p-th program)
+///   * y[i] + dy[i][j] * (1 - r[p]) + dy'[i][j] * r[p] = y[j] (for all i, j in p-th program)
 ///   * z[i] + dz[i][j] = z[j] (for all i, j in the same program)
 ///   * z[i] + sz[i] <= v
 #[warn(dead_code)]
@@ -255,14 +602,17 @@ struct CuboidPackingProblem {
     x: Vec<Variable>,
     y: Vec<Variable>,
     z: Vec<Variable>,
+    r: Vec<Variable>,                     // program_idx -> variable
     a: HashMap<(usize, usize), Variable>, // (cuboid_idx, cuboid_idx) -> variable
     b: HashMap<(usize, usize), Variable>,
     c: HashMap<(usize, usize), Variable>,
     v: Variable,
     cuboid_size: Vec<[usize; 3]>,
+    weights: Vec<f64>, // program_idx -> weight
+    objective: LPObjective,
+    initial_solution: Option<Vec<Schedule>>,
 }
 
-// TODO: Consider rotations
 impl CuboidPackingProblem {
     pub fn new(
         config: PackingConfig,
@@ -298,6 +648,9 @@ impl CuboidPackingProblem {
         let z = (0..num_cuboids)
             .map(|_| vars.add(variable().integer()))
             .collect();
+        let r = (0..num_programs)
+            .map(|_| vars.add(variable().binary()))
+            .collect();
         let mut a = HashMap::new();
         let mut b = HashMap::new();
         let mut c = HashMap::new();
@@ -335,6 +688,7 @@ impl CuboidPackingProblem {
             .collect();
 
         Self {
+            weights: vec![0.; num_programs],
             config,
             fixed_cuboids,
             programs,
@@ -343,17 +697,140 @@ impl CuboidPackingProblem {
             x,
             y,
             z,
+            r,
             a,
             b,
             c,
             v,
             cuboid_size,
+            objective: LPObjective::default(),
+            initial_solution: None,
         }
     }
 
-    pub fn solve(self) -> Vec<Schedule> {
-        let mut problem = LPSolverWrapper::new(self.vars.minimise(self.v), self.config.time_limit);
+    /// Set the weights of the start times of programs in the objective function.
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        assert_eq!(self.weights.len(), weights.len());
+        self.weights = weights;
+        self
+    }
+
+    pub fn with_objective(mut self, objective: LPObjective) -> Self {
+        self.objective = objective;
+        self
+    }
 
+    /// Returns the variables with the entities they are about, which are written with the model.
+    fn variables_info(&self) -> Vec<(Variable, VariableInfo)> {
+        let num_targets = self.x.len();
+        let mut owners = vec![(0, 0); num_targets];
+        for (i, ids) in self.to_cuboid_idx.iter().enumerate() {
+            for (j, id) in ids.iter().enumerate() {
+                owners[*id] = (i, j);
+            }
+        }
+        let entity = |id: usize| {
+            if id < num_targets {
+                let (program, cuboid) = owners[id];
+                ModelEntity::Cuboid { program, cuboid }
+            } else {
+                ModelEntity::FixedCuboid(id - num_targets)
+            }
+        };
+        let id_name = |id: usize| {
+            if id < num_targets {
+                id.to_string()
+            } else {
+                format!("f{}", id - num_targets)
+            }
+        };
+
+        let mut info = Vec::new();
+        for (prefix, vars) in [("x", &self.x), ("y", &self.y), ("z", &self.z)] {
+            for (id, var) in vars.iter().enumerate() {
+                let name = format!("{}_{}", prefix, id);
+                let var_info = VariableInfo::new(name, VariableKind::Integer, vec![entity(id)]);
+                info.push((*var, var_info));
+            }
+        }
+        for (i, var) in self.r.iter().enumerate() {
+            let name = format!("r_{}", i);
+            let entities = vec![ModelEntity::Program(i)];
+            info.push((
+                *var,
+                VariableInfo::new(name, VariableKind::Binary, entities),
+            ));
+        }
+        for (prefix, vars) in [("a", &self.a), ("b", &self.b), ("c", &self.c)] {
+            let mut keys: Vec<_> = vars.keys().collect();
+            keys.sort();
+            for &(id1, id2) in keys {
+                let name = format!("{}_{}_{}", prefix, id_name(id1), id_name(id2));
+                let entities = vec![entity(id1), entity(id2)];
+                let var_info = VariableInfo::new(name, VariableKind::Binary, entities);
+                info.push((vars[&(id1, id2)], var_info));
+            }
+        }
+        info.push((
+            self.v,
+            VariableInfo::new("v".into(), VariableKind::Continuous, vec![]),
+        ));
+        info
+    }
+
+    /// Set the schedules used as the initial solution of the solver.
+    pub fn with_initial_solution(mut self, schedules: Vec<Schedule>) -> Self {
+        assert_eq!(self.programs.len(), schedules.len());
+        self.initial_solution = Some(schedules);
+        self
+    }
+
+    /// Returns the values of x, y, z, r and v for the initial solution. The binary variables for
+    /// the relative positions are left to the solver.
+    fn initial_values(&self, schedules: &[Schedule]) -> Vec<(Variable, f64)> {
+        let mut values = Vec::new();
+        let mut v = 0;
+        for (i, schedule) in schedules.iter().enumerate() {
+            let program = Program::new(ProgramFormat::Cuboid(self.programs[i].clone()));
+            let placed = apply_schedule(&program, schedule);
+            for (j, c) in placed.cuboid().unwrap().iter().enumerate() {
+                let id = self.to_cuboid_idx[i][j];
+                values.push((self.x[id], c.x1() as f64));
+                values.push((self.y[id], c.y1() as f64));
+                values.push((self.z[id], c.z1() as f64));
+                v = v.max(c.z2());
+            }
+            values.push((self.r[i], schedule.rotate as f64));
+        }
+        values.push((self.v, v as f64));
+        values
+    }
+
+    /// Returns the objective function on the cuboid programs.
+    fn objective(&self, compactness_weight: f64, priority_weight: f64) -> Expression {
+        let mut objective = match self.objective {
+            LPObjective::Makespan | LPObjective::MakespanThenCompactness => {
+                Expression::from(self.v)
+            }
+            LPObjective::WeightedCompletion | LPObjective::SumOfStarts => 0.into(),
+        };
+        for (i, cs) in self.programs.iter().enumerate() {
+            // the start and end of the program relative to the first cuboid
+            let z0 = self.z[self.to_cuboid_idx[i][0]];
+            let start = z0 + (cs.iter().map(|c| c.z1()).min().unwrap() - cs[0].z1());
+            let end = z0 + (cs.iter().map(|c| c.z2()).max().unwrap() - cs[0].z1());
+            objective += match self.objective {
+                LPObjective::Makespan => priority_weight * self.weights[i] * z0,
+                LPObjective::WeightedCompletion => (1. + self.weights[i]) * end,
+                LPObjective::SumOfStarts => start,
+                LPObjective::MakespanThenCompactness => compactness_weight * end,
+            };
+        }
+        objective
+    }
+
+    /// Adds the constraints on the cuboid programs to the problem.
+    fn add_constraints(&self, mut problem: LPSolverWrapper) -> LPSolverWrapper {
         let max_x = self.config.size_x as i32; // X
         let max_y = self.config.size_y as i32; // Y
         let max_z = self.config.max_z as i32; // Z
@@ -365,6 +842,10 @@ impl CuboidPackingProblem {
                 let id1 = self.to_cuboid_idx[i1][j1];
                 let [size_xi, size_yi, size_zi] = self.cuboid_size[id1];
                 let (size_xi, size_yi, size_zi) = (size_xi as i32, size_yi as i32, size_zi as i32);
+                let ri = self.r[i1];
+                // the sizes after the rotation
+                let rot_size_xi = size_xi + (size_yi - size_xi) * ri;
+                let rot_size_yi = size_yi + (size_xi - size_yi) * ri;
                 let xi = self.x[id1];
                 let yi = self.y[id1];
                 let zi = self.z[id1];
@@ -373,20 +854,23 @@ impl CuboidPackingProblem {
                     let dx = self.programs[i1][0].x1() - self.programs[i1][j1].x1();
                     let dy = self.programs[i1][0].y1() - self.programs[i1][j1].y1();
                     let dz = self.programs[i1][0].z1() - self.programs[i1][j1].z1();
+                    let (rx0, ry0) = rotated_offset(&self.programs[i1], 0);
+                    let (rxj, ryj) = rotated_offset(&self.programs[i1], j1);
+                    let (rdx, rdy) = (rx0 - rxj, ry0 - ryj);
                     problem = problem
-                        .with(constraint!(self.x[id3] == xi + dx))
-                        .with(constraint!(self.y[id3] == yi + dy))
-                        .with(constraint!(self.z[id3] == zi + dz));
+                        .with(linear_constraint!(self.x[id3] == xi + dx + (rdx - dx) * ri))
+                        .with(linear_constraint!(self.y[id3] == yi + dy + (rdy - dy) * ri))
+                        .with(linear_constraint!(self.z[id3] == zi + dz));
                 }
 
                 problem = problem
-                    .with(constraint!(0 <= xi))
-                    .with(constraint!(xi + size_xi <= max_x))
-                    .with(constraint!(0 <= yi))
-                    .with(constraint!(yi + size_yi <= max_y))
-                    .with(constraint!(self.config.min_z <= zi))
-                    .with(constraint!(zi + size_zi <= max_z))
-                    .with(constraint!(zi + size_zi <= self.v));
+                    .with(linear_constraint!(0 <= xi))
+                    .with(linear_constraint!(xi + rot_size_xi.clone() <= max_x))
+                    .with(linear_constraint!(0 <= yi))
+                    .with(linear_constraint!(yi + rot_size_yi.clone() <= max_y))
+                    .with(linear_constraint!(self.config.min_z <= zi))
+                    .with(linear_constraint!(zi + size_zi <= max_z))
+                    .with(linear_constraint!(zi + size_zi <= self.v));
 
                 // for fixed_cuboids
                 for i2 in 0..self.fixed_cuboids.len() {
@@ -403,14 +887,19 @@ impl CuboidPackingProblem {
                     let size_xj = self.fixed_cuboids[i2].size_x() as i32;
                     let size_yj = self.fixed_cuboids[i2].size_y() as i32;
                     let size_zj = self.fixed_cuboids[i2].size_z() as i32;
-                    problem = problem.with(constraint!(aij + aji + bij + bji + cij + cji >= 1));
+                    problem =
+                        problem.with(linear_constraint!(aij + aji + bij + bji + cij + cji >= 1));
                     problem = problem
-                        .with(constraint!(xi - xj + max_x * aij <= max_x - size_xi))
-                        .with(constraint!(yi - yj + max_y * bij <= max_y - size_yi))
-                        .with(constraint!(zi - zj + max_z * cij <= max_z - size_zi))
-                        .with(constraint!(xj - xi + max_x * aji <= max_x - size_xj))
-                        .with(constraint!(yj - yi + max_y * bji <= max_y - size_yj))
-                        .with(constraint!(zj - zi + max_z * cji <= max_z - size_zj));
+                        .with(linear_constraint!(
+                            xi - xj + max_x * aij <= max_x - rot_size_xi.clone()
+                        ))
+                        .with(linear_constraint!(
+                            yi - yj + max_y * bij <= max_y - rot_size_yi.clone()
+                        ))
+                        .with(linear_constraint!(zi - zj + max_z * cij <= max_z - size_zi))
+                        .with(linear_constraint!(xj - xi + max_x * aji <= max_x - size_xj))
+                        .with(linear_constraint!(yj - yi + max_y * bji <= max_y - size_yj))
+                        .with(linear_constraint!(zj - zi + max_z * cji <= max_z - size_zj));
                 }
 
                 // Other target cuboids
@@ -431,55 +920,469 @@ impl CuboidPackingProblem {
                         let zj = self.z[id2];
 
                         if id1 < id2 {
-                            problem =
-                                problem.with(constraint!(aij + aji + bij + bji + cij + cji >= 1));
+                            problem = problem
+                                .with(linear_constraint!(aij + aji + bij + bji + cij + cji >= 1));
                         }
                         problem = problem
-                            .with(constraint!(xi - xj + max_x * aij <= max_x - size_xi))
-                            .with(constraint!(yi - yj + max_y * bij <= max_y - size_yi))
-                            .with(constraint!(zi - zj + max_z * cij <= max_z - size_zi));
+                            .with(linear_constraint!(
+                                xi - xj + max_x * aij <= max_x - rot_size_xi.clone()
+                            ))
+                            .with(linear_constraint!(
+                                yi - yj + max_y * bij <= max_y - rot_size_yi.clone()
+                            ))
+                            .with(linear_constraint!(zi - zj + max_z * cij <= max_z - size_zi));
                     }
                 }
             }
         }
 
-        let solution = problem.solve().unwrap();
+        problem
+    }
+
+    /// Returns the schedules of the cuboid programs in the solution.
+    fn schedules(&self, solution: &dyn Solution) -> Vec<Schedule> {
         (0..self.programs.len())
             .map(|i| {
                 let id = self.to_cuboid_idx[i][0];
                 let x = solution.value(self.x[id]).round() as i32;
                 let y = solution.value(self.y[id]).round() as i32;
                 let z = solution.value(self.z[id]).round() as i32;
-                let x_orig = self.programs[i][0].x1();
-                let y_orig = self.programs[i][0].y1();
-                let z_orig = self.programs[i][0].z1();
-                Schedule::new(x - x_orig, y - y_orig, z - z_orig, 0, false)
+                let rotate = solution.value(self.r[i]).round() as i32;
+                // `apply_schedule` keeps the minimum corner of the program before the shift.
+                let cs = &self.programs[i];
+                let x_orig = cs.iter().map(|c| c.x1()).min().unwrap();
+                let y_orig = cs.iter().map(|c| c.y1()).min().unwrap();
+                let z_orig = cs[0].z1();
+                let (dx, dy) = if rotate == 1 {
+                    rotated_offset(cs, 0)
+                } else {
+                    (cs[0].x1() - x_orig, cs[0].y1() - y_orig)
+                };
+                Schedule::new(x - dx - x_orig, y - dy - y_orig, z - z_orig, rotate, false)
             })
             .collect()
     }
+
+    /// Returns None if the solver finds no feasible solution.
+    pub fn solve(mut self) -> Option<Vec<Schedule>> {
+        let compactness_weight =
+            LPObjective::compactness_weight(self.programs.len(), self.config.max_z);
+        let priority_weight = LPObjective::priority_weight(&self.weights, self.config.max_z);
+        let objective = self.objective(compactness_weight, priority_weight);
+        let initial_values = self
+            .initial_solution
+            .as_ref()
+            .map(|schedules| self.initial_values(schedules));
+        let recorder = self.config.dump.clone().map(|dump| {
+            let mut recorder = ModelRecorder::new(dump, self.variables_info());
+            recorder.set_objective(&objective).map(|_| recorder)
+        });
+        let vars = std::mem::replace(&mut self.vars, variables!());
+        let mut problem = LPSolverWrapper::new(
+            vars.minimise(objective),
+            self.config.solver,
+            self.config.time_limit,
+        )
+        .with_recorder(recorder);
+        if let Some(values) = initial_values {
+            problem = problem.with_initial_solution(values);
+        }
+        let problem = self.add_constraints(problem);
+
+        let solution = problem.solve().ok()?;
+        Some(self.schedules(solution.as_ref()))
+    }
+}
+
+/// Returns the polycube program shrunk along the z axis by `shrink_ratio`, where each layer of
+/// `shrink_ratio` blocks is merged into one. When it is placed at a multiple of `shrink_ratio`,
+/// the blocks of the original program are covered by those of the shrunk one.
+fn shrink_polycube(program: &Program, shrink_ratio: i32) -> Program {
+    if shrink_ratio == 1 {
+        return program.clone();
+    }
+    let blocks: Voxels = program
+        .blocks()
+        .iter()
+        .map(|b| Coordinate::new(b.x, b.y, b.z.div_euclid(shrink_ratio)))
+        .collect();
+    Program::new(ProgramFormat::Polycube(Polycube::new(
+        blocks.iter().collect(),
+    )))
+}
+
+/// The LP problem when a batch has both polycube and cuboid programs, where cuboid programs come
+/// first. Cuboid programs are modelled as boxes as in `CuboidPackingProblem`, and each polycube
+/// program chooses one of its schedules as in `PolycubePackingProblem`. A cuboid is kept apart
+/// from a polycube program by the bounding box of the chosen schedule, whose coordinates
+/// X1[p], X2[p], ... are linear in the schedule variables.
+/// * Variables:
+///   * d[i][p][k] := k-th disjunct between i-th cuboid and p-th polycube program (binary)
+/// * Constraints in addition to those of the two problems:
+///   * d[i][p][0] + ... + d[i][p][5] >= 1
+///   * x[i] - X1[p] + X * d[i][p][0] <= X - sx'[i] (and similarly for y and z)
+///   * X2[p] - x[i] + X * d[i][p][3] <= X (and similarly for y and z)
+///   * v >= (z + 1) * (the sum of schedule variables using a block at z)
+///
+/// Fixed cuboids are avoided by cuboid programs, and fixed blocks by polycube programs. Schedules
+/// of polycube programs are limited below `polycube_max_z` to keep the model small.
+struct MixedPackingProblem {
+    cuboids: CuboidPackingProblem,
+    programs: Vec<Program>, // polycube programs
+    s_vars: HashMap<ScheduleVarsKey, Variable>,
+    bounding_boxes: HashMap<ScheduleVarsKey, Cuboid>,
+    is_block_present: HashMap<Coordinate, Expression>,
+    s_sums: Vec<Expression>,
+    d: HashMap<(usize, usize), [Variable; 6]>, // (cuboid_idx, polycube program_idx) -> variables
+    z_offsets: Vec<(i32, i32)>, // the start and end of each program relative to its schedule
+    weights: Vec<f64>,          // polycube program_idx -> weight
+    initial_solution: Option<Vec<Schedule>>,
+}
+
+impl MixedPackingProblem {
+    fn new(
+        config: PackingConfig,
+        fixed_cuboids: Vec<Cuboid>,
+        fixed_blocks: Voxels,
+        move_areas: Vec<Cuboid>,
+        cuboids: Vec<Vec<Cuboid>>,
+        programs: Vec<Program>,
+        polycube_max_z: u32,
+    ) -> Self {
+        let polycube_config = PackingConfig {
+            max_z: polycube_max_z.min(config.max_z),
+            ..config.clone()
+        };
+        let mut problem = CuboidPackingProblem::new(config, fixed_cuboids, cuboids);
+        let mut s_vars = HashMap::new();
+        let mut bounding_boxes = HashMap::new();
+        let mut is_block_present: HashMap<_, Expression> = HashMap::new();
+        let mut s_sums = Vec::new();
+        for (i, program) in programs.iter().enumerate() {
+            let candidates =
+                collect_schedule_candidate(&polycube_config, &fixed_blocks, &move_areas, program);
+            let mut s_sum: Expression = 0.into();
+            for (schedule, scheduled) in candidates {
+                let s_var = problem.vars.add(variable().binary());
+                let key = ScheduleVarsKey::new(i, schedule);
+                bounding_boxes.insert(key.clone(), scheduled.bounding_box());
+                s_vars.insert(key, s_var);
+                s_sum += s_var;
+                for block in scheduled.blocks() {
+                    *is_block_present.entry(block).or_default() += s_var;
+                }
+            }
+            s_sums.push(s_sum);
+        }
+
+        let mut d = HashMap::new();
+        for id in 0..problem.x.len() {
+            for p in 0..programs.len() {
+                let vars = std::array::from_fn(|_| problem.vars.add(variable().binary()));
+                d.insert((id, p), vars);
+            }
+        }
+
+        let z_offsets = programs
+            .iter()
+            .map(|p| {
+                let zs: Vec<_> = p.blocks().iter().map(|b| b.z).collect();
+                (*zs.iter().min().unwrap(), zs.iter().max().unwrap() + 1)
+            })
+            .collect();
+        Self {
+            cuboids: problem,
+            weights: vec![0.; programs.len()],
+            programs,
+            s_vars,
+            bounding_boxes,
+            is_block_present,
+            s_sums,
+            d,
+            z_offsets,
+            initial_solution: None,
+        }
+    }
+
+    /// Set the weights of the start times of programs in the objective function.
+    fn with_weights(mut self, mut weights: Vec<f64>) -> Self {
+        let polycube_weights = weights.split_off(self.cuboids.programs.len());
+        assert_eq!(self.weights.len(), polycube_weights.len());
+        self.cuboids = self.cuboids.with_weights(weights);
+        self.weights = polycube_weights;
+        self
+    }
+
+    fn with_objective(mut self, objective: LPObjective) -> Self {
+        self.cuboids = self.cuboids.with_objective(objective);
+        self
+    }
+
+    /// Set the schedules used as the initial solution of the solver.
+    fn with_initial_solution(mut self, mut schedules: Vec<Schedule>) -> Self {
+        let polycube_schedules = schedules.split_off(self.cuboids.programs.len());
+        assert_eq!(self.programs.len(), polycube_schedules.len());
+        self.cuboids = self.cuboids.with_initial_solution(schedules);
+        self.initial_solution = Some(polycube_schedules);
+        self
+    }
+
+    /// Returns the variables with the entities they are about, which are written with the model.
+    fn variables_info(&self) -> Vec<(Variable, VariableInfo)> {
+        let num_cuboid_programs = self.cuboids.programs.len();
+        let mut info = self.cuboids.variables_info();
+        let mut keys: Vec<_> = self.s_vars.keys().collect();
+        keys.sort_by_key(|key| {
+            let s = &key.schedule;
+            (key.i, s.z, s.y, s.x, s.rotate, s.flip)
+        });
+        let mut count = vec![0; self.programs.len()];
+        for key in keys {
+            let program = num_cuboid_programs + key.i;
+            let entity = ModelEntity::Schedule {
+                program,
+                schedule: key.schedule.clone(),
+            };
+            let name = format!("s_{}_{}", program, count[key.i]);
+            count[key.i] += 1;
+            info.push((
+                self.s_vars[key],
+                VariableInfo::new(name, VariableKind::Binary, vec![entity]),
+            ));
+        }
+        for (i, ids) in self.cuboids.to_cuboid_idx.iter().enumerate() {
+            for (j, &id) in ids.iter().enumerate() {
+                for p in 0..self.programs.len() {
+                    let program = num_cuboid_programs + p;
+                    for (k, var) in self.d[&(id, p)].iter().enumerate() {
+                        let name = format!("d_{}_{}_{}", id, program, k);
+                        let entities = vec![
+                            ModelEntity::Cuboid {
+                                program: i,
+                                cuboid: j,
+                            },
+                            ModelEntity::Program(program),
+                        ];
+                        info.push((
+                            *var,
+                            VariableInfo::new(name, VariableKind::Binary, entities),
+                        ));
+                    }
+                }
+            }
+        }
+        info
+    }
+
+    /// Returns the values of the variables of both kinds of programs for the initial solution.
+    /// The schedule variables of a polycube program are omitted if its schedule is not a
+    /// candidate (e.g., it is above `polycube_max_z`).
+    fn initial_values(&self) -> Option<Vec<(Variable, f64)>> {
+        let cuboid_schedules = self.cuboids.initial_solution.as_ref()?;
+        let schedules = self.initial_solution.as_ref()?;
+        let mut values = self.cuboids.initial_values(cuboid_schedules);
+        let mut v = 0;
+        for (i, schedule) in schedules.iter().enumerate() {
+            let key = ScheduleVarsKey::new(i, schedule.clone());
+            if !self.s_vars.contains_key(&key) {
+                continue;
+            }
+            for (other, s_var) in self.s_vars.iter().filter(|(other, _)| other.i == i) {
+                values.push((*s_var, if *other == key { 1. } else { 0. }));
+            }
+            v = v.max(schedule.z + self.z_offsets[i].1);
+        }
+        for (var, value) in &mut values {
+            if *var == self.cuboids.v {
+                *value = value.max(v as f64);
+            }
+        }
+        Some(values)
+    }
+
+    /// Returns None if the solver finds no feasible solution.
+    fn solve(mut self) -> Option<Vec<Schedule>> {
+        let num_programs = self.cuboids.programs.len() + self.programs.len();
+        let compactness_weight =
+            LPObjective::compactness_weight(num_programs, self.cuboids.config.max_z);
+        let weights: Vec<_> = self
+            .cuboids
+            .weights
+            .iter()
+            .chain(&self.weights)
+            .copied()
+            .collect();
+        let priority_weight = LPObjective::priority_weight(&weights, self.cuboids.config.max_z);
+        let mut objective = self.cuboids.objective(compactness_weight, priority_weight);
+        for (key, s_var) in &self.s_vars {
+            let (start_offset, end_offset) = self.z_offsets[key.i];
+            let start = (key.schedule.z + start_offset) as f64;
+            let end = (key.schedule.z + end_offset) as f64;
+            let coef = match self.cuboids.objective {
+                LPObjective::Makespan => {
+                    priority_weight * self.weights[key.i] * key.schedule.z as f64
+                }
+                LPObjective::WeightedCompletion => (1. + self.weights[key.i]) * end,
+                LPObjective::SumOfStarts => start,
+                LPObjective::MakespanThenCompactness => compactness_weight * end,
+            };
+            objective += coef * *s_var;
+        }
+        let initial_values = self.initial_values();
+        let recorder = self.cuboids.config.dump.clone().map(|dump| {
+            let mut recorder = ModelRecorder::new(dump, self.variables_info());
+            recorder.set_objective(&objective).map(|_| recorder)
+        });
+        let vars = std::mem::replace(&mut self.cuboids.vars, variables!());
+        let mut problem = LPSolverWrapper::new(
+            vars.minimise(objective),
+            self.cuboids.config.solver,
+            self.cuboids.config.time_limit,
+        )
+        .with_recorder(recorder);
+        if let Some(values) = initial_values {
+            problem = problem.with_initial_solution(values);
+        }
+        let mut problem = self.cuboids.add_constraints(problem);
+
+        let v = self.cuboids.v;
+        for (pos, is_present) in &self.is_block_present {
+            problem = problem.with(linear_constraint!(is_present.clone() <= 1));
+            problem = problem.with(linear_constraint!(v >= (pos.z + 1) * is_present.clone()));
+        }
+        for s_sum in &self.s_sums {
+            problem = problem.with(linear_constraint!(s_sum.clone() == 1));
+        }
+
+        // [X1, Y1, Z1, X2, Y2, Z2] of the bounding box of each polycube program
+        let mut bounding_boxes: Vec<[Expression; 6]> =
+            vec![std::array::from_fn(|_| 0.into()); self.programs.len()];
+        for (key, s_var) in &self.s_vars {
+            let b = &self.bounding_boxes[key];
+            let coords = [b.x1(), b.y1(), b.z1(), b.x2(), b.y2(), b.z2()];
+            for (e, coord) in bounding_boxes[key.i].iter_mut().zip(coords) {
+                *e += coord as f64 * *s_var;
+            }
+        }
+
+        let max_x = self.cuboids.config.size_x as i32; // X
+        let max_y = self.cuboids.config.size_y as i32; // Y
+        let max_z = self.cuboids.config.max_z as i32; // Z
+        for (i, ids) in self.cuboids.to_cuboid_idx.iter().enumerate() {
+            for &id in ids {
+                let [size_x, size_y, size_z] = self.cuboids.cuboid_size[id];
+                let (size_x, size_y, size_z) = (size_x as i32, size_y as i32, size_z as i32);
+                let r = self.cuboids.r[i];
+                let rot_size_x = size_x + (size_y - size_x) * r;
+                let rot_size_y = size_y + (size_x - size_y) * r;
+                let x = self.cuboids.x[id];
+                let y = self.cuboids.y[id];
+                let z = self.cuboids.z[id];
+                for (p, [x1, y1, z1, x2, y2, z2]) in bounding_boxes.iter().enumerate() {
+                    let [d0, d1, d2, d3, d4, d5] = self.d[&(id, p)];
+                    problem = problem
+                        .with(linear_constraint!(d0 + d1 + d2 + d3 + d4 + d5 >= 1))
+                        .with(linear_constraint!(
+                            x - x1.clone() + max_x * d0 <= max_x - rot_size_x.clone()
+                        ))
+                        .with(linear_constraint!(
+                            y - y1.clone() + max_y * d1 <= max_y - rot_size_y.clone()
+                        ))
+                        .with(linear_constraint!(
+                            z - z1.clone() + max_z * d2 <= max_z - size_z
+                        ))
+                        .with(linear_constraint!(x2.clone() - x + max_x * d3 <= max_x))
+                        .with(linear_constraint!(y2.clone() - y + max_y * d4 <= max_y))
+                        .with(linear_constraint!(z2.clone() - z + max_z * d5 <= max_z));
+                }
+            }
+        }
+
+        let solution = problem.solve().ok()?;
+        let mut schedules = self.cuboids.schedules(solution.as_ref());
+        let mut result = Vec::new();
+        for (key, s_var) in self.s_vars {
+            if f64::abs(solution.value(s_var) - 1.) <= 1e-8 {
+                result.push((key.i, key.schedule));
+            }
+        }
+        if result.len() != self.programs.len() {
+            return None;
+        }
+        result.sort_by(|(i, _), (j, _)| usize::cmp(i, j));
+        schedules.extend(result.into_iter().map(|(_, s)| s));
+        Some(schedules)
+    }
+}
+
+/// The result of the LP scheduler in a scheduling round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LPRound {
+    pub batch_size: usize,
+    /// The value of the objective function for the returned placement (see `LPObjective`).
+    pub objective_value: f64,
+    /// True if the initial solution given by the corner greedy algorithm is returned.
+    pub use_initial_solution: bool,
 }
 
 pub struct LPScheduler {
-    job_list: VecDeque<Job>,
+    job_list: JobQueue,
     config: SimulationConfig,
-    schedule_cycles_sum: u64,
-    scheduled_count: u64,
+    latency_predictor: LatencyPredictor,
+    timer: SchedulingTimer,
+    evaluator: CandidateEvaluator,
+    rounds: Vec<LPRound>,
 }
 
 impl LPScheduler {
     pub fn new(config: SimulationConfig) -> Self {
         Self {
-            job_list: VecDeque::new(),
+            job_list: JobQueue::new(&config.scheduler),
+            latency_predictor: LatencyPredictor::new(config.scheduler.latency_predictor.clone()),
+            timer: SchedulingTimer::new(&config),
+            evaluator: CandidateEvaluator::new(config.scheduler.num_threads),
             config,
-            schedule_cycles_sum: 0,
-            scheduled_count: 0,
+            rounds: Vec::new(),
+        }
+    }
+}
+
+impl LPObjective {
+    /// Returns the value of the objective function for the placement of the jobs. `origin` is
+    /// the scheduling point, and `max_z` is the length of the schedulable window.
+    fn evaluate(
+        &self,
+        jobs: &[Job],
+        schedules: &[Schedule],
+        weights: &[f64],
+        origin: i32,
+        max_z: u32,
+    ) -> f64 {
+        let mut makespan = 0;
+        let mut value = 0.;
+        for ((job, schedule), w) in jobs.iter().zip(schedules).zip(weights) {
+            let bbox = apply_schedule(&job.program, schedule).bounding_box();
+            let (start, end) = ((bbox.z1() - origin) as f64, bbox.z2() - origin);
+            makespan = makespan.max(end);
+            value += match self {
+                LPObjective::Makespan => LPObjective::priority_weight(weights, max_z) * w * start,
+                LPObjective::WeightedCompletion => (1. + w) * end as f64,
+                LPObjective::SumOfStarts => start,
+                LPObjective::MakespanThenCompactness => {
+                    LPObjective::compactness_weight(jobs.len(), max_z) * end as f64
+                }
+            };
+        }
+        match self {
+            LPObjective::Makespan | LPObjective::MakespanThenCompactness => value + makespan as f64,
+            LPObjective::WeightedCompletion | LPObjective::SumOfStarts => value,
         }
     }
 }
 
 impl Scheduler for LPScheduler {
     fn add_job(&mut self, job: Job) {
-        self.job_list.push_back(job);
+        self.job_list.push(job);
     }
 
     fn run(&mut self, env: &Environment) -> Vec<(JobID, Schedule)> {
@@ -487,154 +1390,334 @@ impl Scheduler for LPScheduler {
             return Vec::new();
         }
 
-        let jobs = self.take_jobs_by_batch_size();
+        let jobs = self.job_list.take_batch();
 
-        let zsum = jobs
-            .iter()
-            .map(|job| match job.program.format() {
-                ProgramFormat::Polycube(p) => {
-                    p.blocks().iter().map(|c| c.z).max().unwrap() as u32 + 1
-                }
-                ProgramFormat::Cuboid(cs) => cs.iter().map(|c| c.size_z() as u32).sum(),
-            })
-            .sum::<u32>();
+        let height = |job: &Job| match job.program.format() {
+            ProgramFormat::Polycube(p) => p.blocks().iter().map(|c| c.z).max().unwrap() as u32 + 1,
+            ProgramFormat::Cuboid(cs) => cs.iter().map(|c| c.size_z() as u32).sum(),
+        };
+        let zsum = jobs.iter().map(height).sum::<u32>();
 
-        let schedule_point =
-            (env.global_pc() + self.schedule_cycles_sum / u64::max(1, self.scheduled_count)) as i32;
+        let est_scheduling_cost = self.latency_predictor.predict();
+        let schedule_point = (env.global_pc() + est_scheduling_cost) as i32;
 
-        let max_z = if env.end_pc() < schedule_point as u64 {
-            zsum
-        } else {
-            zsum + (env.end_pc() - schedule_point as u64) as u32
+        // the length of running programs after the scheduling point
+        let running_z = env.end_pc().saturating_sub(schedule_point as u64) as u32;
+        let max_z = zsum + running_z;
+
+        self.timer.start();
+
+        // The placement by the corner greedy algorithm is used as the initial solution of the
+        // solver, and also as the result if the solver fails or finds only a worse one.
+        let greedy_schedules =
+            place_jobs_at_corners(env, &self.evaluator, schedule_point as u64, &jobs);
+        let origin = schedule_point;
+
+        // Cuboid programs come first in a mixed batch as `MixedPackingProblem` expects.
+        let (jobs, greedy_schedules): (Vec<_>, Vec<_>) = {
+            let mut pairs: Vec<_> = jobs.into_iter().zip(greedy_schedules).collect();
+            pairs.sort_by_key(|(job, _)| job.program.is_polycube());
+            pairs.into_iter().unzip()
         };
+        let is_cuboid_batch = jobs.iter().all(|job| job.program.is_cuboid());
+        let is_polycube_batch = jobs.iter().all(|job| job.program.is_polycube());
 
         // FIXME: If the z value is too large, errors will occur in the solver (or inside the
         // wrapper library) due to floating-point precision. This is a workaround to prevent that.
-        let shrink_ratio = max_z / 100_000 + 1;
+        // Polycube programs in a mixed batch are shrunk to whole layers (see `shrink_polycube`),
+        // while a batch of only polycube programs is not shrunk to keep the voxel model exact.
+        let shrink_ratio = if is_polycube_batch {
+            1
+        } else {
+            max_z / 100_000 + 1
+        };
 
         let max_z = max_z.div_ceil(shrink_ratio) + jobs.len() as u32;
         let schedule_point = (schedule_point + shrink_ratio as i32 - 1) / (shrink_ratio as i32);
 
-        let pack_cfg = PackingConfig {
+        let mut pack_cfg = PackingConfig {
+            solver: self.config.scheduler.solver.unwrap_or_default(),
             time_limit: self.config.scheduler.time_limit,
             size_x: self.config.size_x,
             size_y: self.config.size_y,
             min_z: 0,
             max_z,
+            dump: None,
+        };
+        let dump_dir = self.config.scheduler.model_dump_dir.clone();
+        let round = self.rounds.len();
+        let job_ids: Vec<_> = jobs.iter().map(|job| job.id).collect();
+        let model_dump = |fixed_cuboids: Vec<Cuboid>, fixed_blocks: &Voxels| {
+            dump_dir.map(|dir| {
+                let mut fixed_blocks: Vec<_> = fixed_blocks.iter().collect();
+                fixed_blocks.sort();
+                ModelDump {
+                    path: dir.join(format!("round_{:05}", round)),
+                    format: self.config.scheduler.model_dump_format.unwrap_or_default(),
+                    job_ids,
+                    fixed_cuboids,
+                    fixed_blocks,
+                }
+            })
         };
 
-        let problem = if jobs.iter().all(|job| job.program.is_polycube()) {
-            let programs = jobs.iter().map(|job| job.program.clone()).collect();
-            PackingProblem::Polycube(PolycubePackingProblem::new(pack_cfg, programs))
-        } else if jobs.iter().all(|job| job.program.is_cuboid()) {
-            let shrink_cuboid = |c: &Cuboid, ref_point: i32| {
-                let sr = shrink_ratio as i32;
-                let z2 = (c.z2() + sr - 1) / sr;
-                let z1 = c.z1() / sr;
-                let size_z = z2 - z1;
-                let (z1, size_z) = if z1 < ref_point {
-                    let size_z = size_z - (ref_point - z1);
-                    (0, size_z)
-                } else {
-                    let z1 = i32::max(0, z1 - ref_point);
-                    (z1, size_z)
-                };
-                Cuboid::new(
-                    Coordinate::new(c.x1(), c.y1(), z1),
-                    c.size_x(),
-                    c.size_y(),
-                    size_z as usize,
-                )
+        let objective = self.config.scheduler.lp_objective.unwrap_or_default();
+        // Jobs with higher priority are placed earlier. Priorities are shifted so that the least
+        // one in the batch has the weight 0.
+        let min_priority = jobs.iter().map(|job| job.priority).min().unwrap_or(0);
+        let weights: Vec<_> = jobs
+            .iter()
+            .map(|job| (job.priority as i64 - min_priority as i64) as f64)
+            .collect();
+        let initial_solution = greedy_schedules
+            .iter()
+            .map(|s| {
+                let z = (s.z + shrink_ratio as i32 - 1) / (shrink_ratio as i32) - schedule_point;
+                Schedule::new(s.x, s.y, z, s.rotate, s.flip)
+            })
+            .collect();
+
+        let sr = shrink_ratio as i32;
+        let shrink_cuboid = |c: &Cuboid, ref_point: i32| {
+            let z2 = (c.z2() + sr - 1) / sr;
+            let z1 = c.z1() / sr;
+            let size_z = z2 - z1;
+            let (z1, size_z) = if z1 < ref_point {
+                let size_z = size_z - (ref_point - z1);
+                (0, size_z)
+            } else {
+                let z1 = i32::max(0, z1 - ref_point);
+                (z1, size_z)
             };
+            Cuboid::new(
+                Coordinate::new(c.x1(), c.y1(), z1),
+                c.size_x(),
+                c.size_y(),
+                size_z as usize,
+            )
+        };
 
+        let problem = if !is_polycube_batch {
+            // Running polycube programs are avoided by their bounding boxes.
+            let is_ahead = |c: &Cuboid| schedule_point < (c.z2() + sr - 1) / sr;
+            let running_polycubes: Vec<_> = env
+                .running_programs()
+                .iter()
+                .filter(|p| p.is_polycube())
+                .collect();
             let mut fixed_cuboids: Vec<_> = env
                 .running_programs()
                 .iter()
-                .flat_map(|p| {
-                    p.cuboid().unwrap().iter().filter_map(|c| {
-                        let sr = shrink_ratio as i32;
-                        let z2 = (c.z2() + sr - 1) / sr;
-                        if schedule_point < z2 {
-                            let c = shrink_cuboid(c, schedule_point);
-                            Some(c)
-                        } else {
-                            None
-                        }
-                    })
-                })
+                .filter_map(|p| p.cuboid())
+                .flatten()
+                .chain(env.defrag_move_areas())
+                .filter(|c| is_ahead(c))
+                .map(|c| shrink_cuboid(c, schedule_point))
                 .collect();
-
-            for move_region in env.defrag_move_areas() {
-                let sr = shrink_ratio as i32;
-                let z2 = (move_region.z2() + sr - 1) / sr;
-                if schedule_point < z2 {
-                    let c = shrink_cuboid(move_region, schedule_point);
-                    fixed_cuboids.push(c);
-                }
-            }
+            // Polycube programs can be placed on top of running ones below `polycube_max_z`.
+            let polycube_max_z = running_z.div_ceil(shrink_ratio)
+                + jobs
+                    .iter()
+                    .filter(|job| job.program.is_polycube())
+                    .map(|job| height(job).div_ceil(shrink_ratio) + 1)
+                    .sum::<u32>();
+            let fixed_blocks: Voxels = if is_cuboid_batch {
+                Voxels::new()
+            } else {
+                let in_window = |z: i32| 0 <= z && z < polycube_max_z as i32;
+                let cuboid_blocks = fixed_cuboids.iter().flat_map(|c| {
+                    (c.z1()..c.z2())
+                        .filter(|&z| in_window(z))
+                        .flat_map(move |z| {
+                            (c.x1()..c.x2())
+                                .flat_map(move |x| (c.y1()..c.y2()).map(move |y| (x, y, z)))
+                        })
+                });
+                let polycube_blocks = running_polycubes.iter().flat_map(|p| {
+                    p.blocks()
+                        .into_iter()
+                        .map(|b| (b.x, b.y, b.z.div_euclid(sr) - schedule_point))
+                        .filter(|&(_, _, z)| in_window(z))
+                });
+                cuboid_blocks
+                    .chain(polycube_blocks)
+                    .map(|(x, y, z)| Coordinate::new(x, y, z))
+                    .collect()
+            };
+            fixed_cuboids.extend(
+                running_polycubes
+                    .iter()
+                    .map(|p| p.bounding_box())
+                    .filter(is_ahead)
+                    .map(|c| shrink_cuboid(&c, schedule_point)),
+            );
 
             let cuboids = jobs
                 .iter()
-                .map(|p| {
-                    p.program
-                        .cuboid()
-                        .unwrap()
-                        .iter()
-                        .map(|c| shrink_cuboid(c, 0))
-                        .collect()
-                })
+                .filter_map(|job| job.program.cuboid())
+                .map(|cs| cs.iter().map(|c| shrink_cuboid(c, 0)).collect())
                 .collect();
 
-            PackingProblem::Cuboid(CuboidPackingProblem::new(pack_cfg, fixed_cuboids, cuboids))
+            pack_cfg.dump = model_dump(fixed_cuboids.clone(), &fixed_blocks);
+            if is_cuboid_batch {
+                PackingProblem::Cuboid(
+                    CuboidPackingProblem::new(pack_cfg, fixed_cuboids, cuboids)
+                        .with_weights(weights.clone())
+                        .with_objective(objective)
+                        .with_initial_solution(initial_solution),
+                )
+            } else {
+                // A move area splits a layer if it is not at a multiple of `shrink_ratio`, in
+                // which case the layer is fixed in the area (see `shrink_cuboid`).
+                let move_areas: Vec<_> = env
+                    .defrag_move_areas()
+                    .iter()
+                    .filter(|m| schedule_point < (m.z1() + sr - 1) / sr)
+                    .map(|m| {
+                        let z = (m.z1() + sr - 1) / sr - schedule_point;
+                        let pos = Coordinate::new(m.x1(), m.y1(), z);
+                        Cuboid::new(pos, m.size_x(), m.size_y(), 0)
+                    })
+                    .collect();
+                let polycubes = jobs
+                    .iter()
+                    .filter(|job| job.program.is_polycube())
+                    .map(|job| shrink_polycube(&job.program, sr))
+                    .collect();
+                PackingProblem::Mixed(
+                    MixedPackingProblem::new(
+                        pack_cfg,
+                        fixed_cuboids,
+                        fixed_blocks,
+                        move_areas,
+                        cuboids,
+                        polycubes,
+                        polycube_max_z,
+                    )
+                    .with_weights(weights.clone())
+                    .with_objective(objective)
+                    .with_initial_solution(initial_solution),
+                )
+            }
         } else {
-            panic!("unsupported");
+            // Polycube programs are packed block by block
+            let shift = |b: Coordinate| Coordinate::new(b.x, b.y, b.z - schedule_point);
+            let fixed_blocks: Voxels = env
+                .running_programs()
+                .iter()
+                .flat_map(|p| p.blocks())
+                .filter(|b| schedule_point <= b.z && b.z < schedule_point + max_z as i32)
+                .map(shift)
+                .collect();
+            let move_areas: Vec<_> = env
+                .defrag_move_areas()
+                .iter()
+                .filter(|m| schedule_point < m.z1())
+                .map(|m| {
+                    let pos = Coordinate::new(m.x1(), m.y1(), m.z1() - schedule_point);
+                    Cuboid::new(pos, m.size_x(), m.size_y(), 0)
+                })
+                .collect();
+            let programs = jobs.iter().map(|job| job.program.clone()).collect();
+            pack_cfg.dump = model_dump(move_areas.clone(), &fixed_blocks);
+            PackingProblem::Polycube(
+                PolycubePackingProblem::new(pack_cfg, fixed_blocks, move_areas, programs)
+                    .with_weights(weights.clone())
+                    .with_objective(objective)
+                    .with_initial_solution(initial_solution),
+            )
         };
 
-        let start = Instant::now();
+        // The work is the number of candidates evaluated for the initial solution and the
+        // number of variables of the model.
+        let work = self.evaluator.take_evaluated() + problem.num_variables() as u64;
+        self.timer.add_work(work);
         let schedules = problem.solve();
-        let elapsed = start
-            .elapsed()
-            .as_micros()
-            .div_ceil(self.config.micro_sec_per_cycle.into());
-        self.schedule_cycles_sum += elapsed as u64;
-        self.scheduled_count += 1;
 
         // The schedules calculated with an empty environment, it is necessary to shift their z
         // position by the maximum z point of issued programs.
-        let schedules: Vec<_> = schedules
-            .into_iter()
-            .map(|s| {
-                Schedule::new(
-                    s.x,
-                    s.y,
-                    (s.z + schedule_point) * (shrink_ratio as i32),
-                    s.rotate,
-                    s.flip,
-                )
-            })
-            .collect();
+        let schedules = schedules.map(|schedules| {
+            schedules
+                .into_iter()
+                .map(|s| {
+                    Schedule::new(
+                        s.x,
+                        s.y,
+                        (s.z + schedule_point) * (shrink_ratio as i32),
+                        s.rotate,
+                        s.flip,
+                    )
+                })
+                .collect::<Vec<_>>()
+        });
+        let window = max_z * shrink_ratio;
+        let evaluate =
+            |schedules: &[Schedule]| objective.evaluate(&jobs, schedules, &weights, origin, window);
+        let greedy_value = evaluate(&greedy_schedules);
+        let solution = schedules.map(|schedules| {
+            let value = evaluate(&schedules);
+            (schedules, value)
+        });
+        let (schedules, objective_value, use_initial_solution) = match solution {
+            Some((schedules, value)) if value <= greedy_value => (schedules, value, false),
+            _ => (greedy_schedules, greedy_value, true),
+        };
+        tracing::debug!(
+            "LP objective ({:?}) = {}{}",
+            objective,
+            objective_value,
+            if use_initial_solution {
+                " (initial solution)"
+            } else {
+                ""
+            }
+        );
+        self.rounds.push(LPRound {
+            batch_size: jobs.len(),
+            objective_value,
+            use_initial_solution,
+        });
+
+        let elapsed = self.timer.stop(jobs.len());
+        self.latency_predictor.observe(elapsed);
+        self.job_list.observe_latency(elapsed, est_scheduling_cost);
 
         jobs.into_iter().map(|job| job.id).zip(schedules).collect()
     }
-}
 
-impl LPScheduler {
-    fn take_jobs_by_batch_size(&mut self) -> Vec<Job> {
-        let take_len = if let Some(batch_size) = self.config.scheduler.batch_size {
-            usize::min(self.job_list.len(), batch_size as usize)
-        } else {
-            self.job_list.len()
-        };
-        let mut taken_jobs = self.job_list.split_off(take_len);
-        std::mem::swap(&mut taken_jobs, &mut self.job_list);
-        taken_jobs.into()
+    fn report(&self) -> Option<serde_json::Value> {
+        let fallback_count = self
+            .rounds
+            .iter()
+            .filter(|r| r.use_initial_solution)
+            .count();
+        Some(serde_json::json!({
+            "objective": self.config.scheduler.lp_objective.unwrap_or_default(),
+            "fallback_count": fallback_count,
+            "rounds": self.rounds,
+        }))
+    }
+
+    fn latency_rounds(&self) -> Option<&[LatencyRound]> {
+        Some(self.latency_predictor.rounds())
+    }
+
+    fn scheduling_cycles(&self) -> Option<u64> {
+        self.timer.last_cycles()
+    }
+
+    fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        self.job_list.batch_rounds()
     }
 }
 
 #[cfg(test)]
 pub mod test {
-    use crate::program::{Coordinate, Cuboid, Polycube, Program, ProgramFormat};
-    use crate::scheduler::lp_scheduler::{CuboidPackingProblem, PackingConfig};
+
+    use crate::program::{
+        is_overlap, Coordinate, Cuboid, Polycube, Program, ProgramFormat, Voxels,
+    };
+    use crate::scheduler::lp_scheduler::{CuboidPackingProblem, LPSolver, PackingConfig};
     use crate::scheduler::{apply_schedule, apply_schedule_to_cuboid};
 
     #[test]
@@ -642,11 +1725,13 @@ pub mod test {
         use crate::scheduler::lp_scheduler::PolycubePackingProblem;
 
         let config = PackingConfig {
+            solver: LPSolver::default(),
             time_limit: Some(60),
             size_x: 4,
             size_y: 3,
             min_z: 0,
             max_z: 8,
+            dump: None,
         };
         let format = ProgramFormat::Polycube(Polycube::from(&[
             (0, 0, 0),
@@ -676,8 +1761,13 @@ pub mod test {
         ]));
         let programs: Vec<_> = (0..2).map(|_| Program::new(format.clone())).collect();
 
-        let problem = PolycubePackingProblem::new(config.clone(), programs.clone());
-        let result = problem.solve();
+        let problem = PolycubePackingProblem::new(
+            config.clone(),
+            Voxels::new(),
+            Vec::new(),
+            programs.clone(),
+        );
+        let result = problem.solve().unwrap();
         assert_eq!(programs.len(), result.len());
         let scheduled: Vec<_> = programs
             .into_iter()
@@ -718,32 +1808,37 @@ pub mod test {
         ];
 
         let config = PackingConfig {
+            solver: LPSolver::default(),
             time_limit: Some(60),
             size_x: 2,
             size_y: 2,
             min_z: 0,
             max_z: 2,
+            dump: None,
         };
 
         let problem = CuboidPackingProblem::new(config.clone(), Vec::new(), programs.clone());
-        let schedule = problem.solve();
+        let schedule = problem.solve().unwrap();
+        let results: Vec<_> = (0..programs.len())
+            .map(|i| apply_schedule_to_cuboid(&programs[i][0], &schedule[i]))
+            .collect();
         for i in 0..programs.len() {
-            let xi = schedule[i].x;
-            let yi = schedule[i].y;
-            let zi = schedule[i].z;
-            let size_xi = programs[i][0].size_x() as i32;
-            let size_yi = programs[i][0].size_y() as i32;
-            let size_zi = programs[i][0].size_z() as i32;
+            let xi = results[i].x1();
+            let yi = results[i].y1();
+            let zi = results[i].z1();
+            let size_xi = results[i].size_x() as i32;
+            let size_yi = results[i].size_y() as i32;
+            let size_zi = results[i].size_z() as i32;
             assert!(0 <= xi && xi + size_xi <= config.size_x as i32);
             assert!(0 <= yi && yi + size_yi <= config.size_y as i32);
             assert!(0 <= zi && zi + size_zi <= config.max_z as i32);
-            for j in i + 1..programs.len() {
-                let xj = schedule[j].x;
-                let yj = schedule[j].y;
-                let zj = schedule[j].z;
-                let size_xj = programs[j][0].size_x() as i32;
-                let size_yj = programs[j][0].size_y() as i32;
-                let size_zj = programs[j][0].size_z() as i32;
+            for result_j in results.iter().skip(i + 1) {
+                let xj = result_j.x1();
+                let yj = result_j.y1();
+                let zj = result_j.z1();
+                let size_xj = result_j.size_x() as i32;
+                let size_yj = result_j.size_y() as i32;
+                let size_zj = result_j.size_z() as i32;
                 let is_overlap_x = !(xi + size_xi <= xj || xj + size_xj <= xi);
                 let is_overlap_y = !(yi + size_yi <= yj || yj + size_yj <= yi);
                 let is_overlap_z = !(zi + size_zi <= zj || zj + size_zj <= zi);
@@ -766,22 +1861,24 @@ pub mod test {
         ];
 
         let config = PackingConfig {
+            solver: LPSolver::default(),
             time_limit: Some(60),
             size_x: 2,
             size_y: 1,
             max_z: 2,
             min_z: 0,
+            dump: None,
         };
 
         let problem = CuboidPackingProblem::new(config.clone(), Vec::new(), programs.clone());
-        let schedule = problem.solve();
+        let schedule = problem.solve().unwrap();
         let results: Vec<_> = programs
             .into_iter()
             .enumerate()
             .flat_map(|(i, cs)| {
-                let schedule = &schedule[i];
-                cs.into_iter()
-                    .map(|c| apply_schedule_to_cuboid(&c, schedule))
+                let program = Program::new(ProgramFormat::Cuboid(cs));
+                let scheduled = apply_schedule(&program, &schedule[i]);
+                scheduled.cuboid().unwrap().clone()
             })
             .collect();
 
@@ -809,4 +1906,436 @@ pub mod test {
             }
         }
     }
+
+    #[test]
+    fn test_lp_schedule_candidate_orientations() {
+        use crate::scheduler::lp_scheduler::collect_schedule_candidate;
+        use crate::scheduler::Schedule;
+        use std::collections::HashSet;
+
+        let config = PackingConfig {
+            solver: LPSolver::default(),
+            time_limit: Some(60),
+            size_x: 2,
+            size_y: 2,
+            min_z: 0,
+            max_z: 2,
+            dump: None,
+        };
+        // A k-cuboid program without symmetry: a 2x1 cuboid with a block on one end above it
+        let program = Program::new(ProgramFormat::Cuboid(vec![
+            Cuboid::new(Coordinate::new(0, 0, 0), 2, 1, 1),
+            Cuboid::new(Coordinate::new(0, 1, 1), 1, 1, 1),
+        ]));
+        let candidates = collect_schedule_candidate(&config, &Voxels::new(), &[], &program);
+
+        // The program fits in the chip only at the origin, so the candidates are the distinct
+        // placements over all rotations with and without flipping.
+        let sorted_blocks = |p: &Program| {
+            let mut blocks = p.blocks();
+            blocks.sort();
+            blocks
+        };
+        let expected: HashSet<_> = (0..4)
+            .flat_map(|r| [false, true].map(|f| Schedule::new(0, 0, 0, r, f)))
+            .map(|s| sorted_blocks(&apply_schedule(&program, &s)))
+            .collect();
+        let placements: HashSet<_> = candidates.iter().map(|(_, p)| sorted_blocks(p)).collect();
+        assert_eq!(placements.len(), candidates.len());
+        assert_eq!(placements, expected);
+        assert!(candidates.iter().any(|(s, _)| s.rotate == 3));
+        assert!(candidates.iter().any(|(s, _)| s.flip));
+
+        // A single cuboid has only 2 different orientations.
+        let cuboid = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(0, 0, 0),
+            2,
+            1,
+            2,
+        )]));
+        let candidates = collect_schedule_candidate(&config, &Voxels::new(), &[], &cuboid);
+        assert_eq!(candidates.len(), 4);
+    }
+
+    #[test]
+    fn test_lp_cuboid_rotation() {
+        // Both programs fit in the chip only if they are rotated.
+        let programs = vec![
+            vec![Cuboid::new(Coordinate::new(0, 0, 0), 1, 2, 1)],
+            vec![
+                Cuboid::new(Coordinate::new(0, 0, 0), 1, 1, 1),
+                Cuboid::new(Coordinate::new(0, 1, 1), 1, 1, 1),
+            ],
+        ];
+
+        let config = PackingConfig {
+            solver: LPSolver::default(),
+            time_limit: Some(60),
+            size_x: 2,
+            size_y: 1,
+            min_z: 0,
+            max_z: 3,
+            dump: None,
+        };
+
+        let problem = CuboidPackingProblem::new(config.clone(), Vec::new(), programs.clone());
+        let schedule = problem.solve().unwrap();
+        assert!(schedule.iter().all(|s| s.rotate == 1));
+        let results: Vec<_> = programs
+            .into_iter()
+            .zip(&schedule)
+            .map(|(cs, s)| apply_schedule(&Program::new(ProgramFormat::Cuboid(cs)), s))
+            .collect();
+        for (i, p) in results.iter().enumerate() {
+            for c in p.cuboid().unwrap() {
+                assert!(0 <= c.x1() && c.x2() <= config.size_x as i32);
+                assert!(0 <= c.y1() && c.y2() <= config.size_y as i32);
+                assert!(0 <= c.z1() && c.z2() <= config.max_z as i32);
+            }
+            for q in results.iter().skip(i + 1) {
+                assert!(!is_overlap(p, q));
+            }
+        }
+    }
+
+    #[test]
+    fn test_lp_mixed_programs() {
+        use crate::scheduler::lp_scheduler::PolycubePackingProblem;
+
+        let config = PackingConfig {
+            solver: LPSolver::default(),
+            time_limit: Some(60),
+            size_x: 2,
+            size_y: 2,
+            min_z: 0,
+            max_z: 3,
+            dump: None,
+        };
+        let programs = vec![
+            Program::new(ProgramFormat::Polycube(Polycube::from(&[
+                (0, 0, 0),
+                (1, 0, 0),
+                (0, 0, 1),
+            ]))),
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                2,
+                2,
+                1,
+            )])),
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                1,
+                2,
+                1,
+            )])),
+        ];
+
+        let problem = PolycubePackingProblem::new(
+            config.clone(),
+            Voxels::new(),
+            Vec::new(),
+            programs.clone(),
+        );
+        let result = problem.solve().unwrap();
+        assert_eq!(programs.len(), result.len());
+        let scheduled: Vec<_> = programs
+            .iter()
+            .zip(&result)
+            .map(|(p, s)| apply_schedule(p, s))
+            .collect();
+        assert!(scheduled[0].is_polycube() && scheduled[1].is_cuboid());
+        for (i, p) in scheduled.iter().enumerate() {
+            assert!(p.blocks().iter().all(|b| {
+                0 <= b.x
+                    && (b.x as u32) < config.size_x
+                    && 0 <= b.y
+                    && (b.y as u32) < config.size_y
+                    && 0 <= b.z
+                    && (b.z as u32) < config.max_z
+            }));
+            for q in scheduled.iter().skip(i + 1) {
+                assert!(!is_overlap(p, q));
+            }
+        }
+    }
+
+    #[test]
+    fn test_lp_polycube_with_fixed_blocks() {
+        use crate::scheduler::lp_scheduler::PolycubePackingProblem;
+
+        let config = PackingConfig {
+            solver: LPSolver::default(),
+            time_limit: Some(60),
+            size_x: 2,
+            size_y: 2,
+            min_z: 0,
+            max_z: 3,
+            dump: None,
+        };
+        let programs = vec![Program::new(ProgramFormat::Polycube(Polycube::from(&[
+            (0, 0, 0),
+            (0, 0, 1),
+        ])))];
+        // Only (1, 1) is free at z = 0, but a move area is there at z = 1.
+        let fixed_blocks: Voxels = [(0, 0), (0, 1), (1, 0)]
+            .into_iter()
+            .map(|(x, y)| Coordinate::new(x, y, 0))
+            .collect();
+        let move_areas = vec![Cuboid::new(Coordinate::new(1, 1, 1), 1, 1, 0)];
+
+        let problem = PolycubePackingProblem::new(
+            config.clone(),
+            fixed_blocks.clone(),
+            move_areas,
+            programs.clone(),
+        );
+        let result = problem.solve().unwrap();
+        let scheduled = apply_schedule(&programs[0], &result[0]);
+        let blocks = scheduled.blocks();
+        assert!(blocks.iter().all(|b| !fixed_blocks.contains(b)));
+        assert_eq!(blocks.iter().map(|b| b.z).min(), Some(1));
+    }
+
+    #[test]
+    fn test_lp_scheduler_avoids_running_programs() {
+        use crate::config::SimulationConfig;
+        use crate::environment::Environment;
+        use crate::job::Job;
+        use crate::scheduler::lp_scheduler::LPScheduler;
+        use crate::scheduler::Scheduler;
+        use crate::test_utils;
+
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.size_x = 2;
+        config.size_y = 2;
+        let mut env = Environment::new(config.clone());
+        assert!(
+            env.issue_program(&Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                1,
+                2,
+                10,
+            )])))
+        );
+
+        // The program requires 2x2 blocks, so it cannot be executed until the running one ends.
+        let program = Program::new(ProgramFormat::Polycube(Polycube::from(&[
+            (0, 0, 0),
+            (1, 0, 0),
+            (0, 1, 0),
+        ])));
+        let mut scheduler = LPScheduler::new(config);
+        scheduler.add_job(Job::new(0, 0, program.clone()));
+        let schedules = scheduler.run(&env);
+
+        assert_eq!(schedules.len(), 1);
+        let scheduled = apply_schedule(&program, &schedules[0].1);
+        assert!(env.can_issue(&scheduled));
+        assert_eq!(schedules[0].1.z, 10);
+    }
+
+    #[test]
+    fn test_lp_scheduler_mixed_batch_with_large_cuboid() {
+        use crate::config::SimulationConfig;
+        use crate::environment::Environment;
+        use crate::job::Job;
+        use crate::scheduler::lp_scheduler::LPScheduler;
+        use crate::scheduler::Scheduler;
+        use crate::test_utils;
+
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.size_x = 2;
+        config.size_y = 2;
+        config.scheduler.batch_size = None;
+        let env = Environment::new(config.clone());
+
+        // The cuboid is too long to be voxelized, and the z axis is shrunk by 3 in the model.
+        let programs = [
+            Program::new(ProgramFormat::Polycube(Polycube::from(&[
+                (0, 0, 0),
+                (0, 1, 0),
+                (0, 0, 1),
+            ]))),
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                1,
+                2,
+                200_000,
+            )])),
+        ];
+        let mut scheduler = LPScheduler::new(config);
+        for (i, program) in programs.iter().enumerate() {
+            scheduler.add_job(Job::new(i as u32, 0, program.clone()));
+        }
+        let mut schedules = scheduler.run(&env);
+        schedules.sort_by_key(|(id, _)| *id);
+
+        // Both programs start immediately side by side.
+        let scheduled: Vec<_> = programs
+            .iter()
+            .zip(&schedules)
+            .map(|(p, (_, s))| apply_schedule(p, s))
+            .collect();
+        assert!(schedules.iter().all(|(_, s)| s.z == 0));
+        assert!(!is_overlap(&scheduled[0], &scheduled[1]));
+        assert!(scheduled.iter().all(|p| env.can_issue(p)));
+        assert!(!scheduler.rounds[0].use_initial_solution);
+    }
+
+    #[test]
+    fn test_lp_scheduler_improves_initial_solution() {
+        use crate::config::SimulationConfig;
+        use crate::environment::Environment;
+        use crate::job::Job;
+        use crate::scheduler::corner_greedy_scheduler::place_jobs_at_corners;
+        use crate::scheduler::lp_scheduler::{LPObjective, LPScheduler};
+        use crate::scheduler::parallel::CandidateEvaluator;
+        use crate::scheduler::Scheduler;
+        use crate::test_utils;
+
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.size_x = 2;
+        config.size_y = 2;
+        config.scheduler.batch_size = None;
+        let env = Environment::new(config.clone());
+
+        let cuboid = |sx, sy, sz| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                sx,
+                sy,
+                sz,
+            )]))
+        };
+        let jobs: Vec<_> = [cuboid(1, 2, 1), cuboid(2, 2, 3), cuboid(1, 2, 3)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| Job::new(i as u32, 0, p))
+            .collect();
+        let weights = vec![0.; jobs.len()];
+        let greedy = place_jobs_at_corners(&env, &CandidateEvaluator::sequential(), 0, &jobs);
+
+        let mut scheduler = LPScheduler::new(config);
+        for job in &jobs {
+            scheduler.add_job(job.clone());
+        }
+        let schedules: Vec<_> = scheduler.run(&env).into_iter().map(|(_, s)| s).collect();
+
+        // The corner greedy algorithm places the 2x2 program between the others.
+        let evaluate = |s: &[_]| LPObjective::Makespan.evaluate(&jobs, s, &weights, 0, 7);
+        assert_eq!(evaluate(&greedy), 7.);
+        assert_eq!(evaluate(&schedules), 6.);
+        assert!(!scheduler.rounds[0].use_initial_solution);
+    }
+
+    #[test]
+    fn test_lp_objectives() {
+        use crate::scheduler::lp_scheduler::{LPObjective, PolycubePackingProblem};
+
+        let config = |size_x| PackingConfig {
+            solver: LPSolver::default(),
+            time_limit: Some(60),
+            size_x,
+            size_y: 1,
+            min_z: 0,
+            max_z: 4,
+            dump: None,
+        };
+        let long = Cuboid::new(Coordinate::new(0, 0, 0), 1, 1, 3);
+        let short = Cuboid::new(Coordinate::new(0, 0, 0), 1, 1, 1);
+        let programs = vec![vec![long.clone()], vec![short.clone()]];
+        let start_z = |objective| {
+            let problem = CuboidPackingProblem::new(config(1), vec![], programs.clone())
+                .with_objective(objective);
+            let schedules = problem.solve().unwrap();
+            schedules.iter().map(|s| s.z).collect::<Vec<_>>()
+        };
+        // The short program is executed first to minimize the response times.
+        assert_eq!(start_z(LPObjective::SumOfStarts), vec![1, 0]);
+        assert_eq!(start_z(LPObjective::WeightedCompletion), vec![1, 0]);
+
+        // The same in the voxel model
+        let polycubes: Vec<_> = programs
+            .iter()
+            .map(|cs| Program::new(ProgramFormat::Cuboid(cs.clone())))
+            .collect();
+        let problem = PolycubePackingProblem::new(config(1), Voxels::new(), Vec::new(), polycubes)
+            .with_objective(LPObjective::SumOfStarts);
+        let schedules = problem.solve().unwrap();
+        assert_eq!((schedules[0].z, schedules[1].z), (1, 0));
+
+        // Both programs start at z = 0 although the makespan is the same if the short one
+        // starts at z = 1.
+        let programs = vec![
+            vec![Cuboid::new(Coordinate::new(0, 0, 0), 1, 1, 2)],
+            vec![short],
+        ];
+        let problem = CuboidPackingProblem::new(config(2), vec![], programs)
+            .with_objective(LPObjective::MakespanThenCompactness);
+        let schedules = problem.solve().unwrap();
+        assert_eq!((schedules[0].z, schedules[1].z), (0, 0));
+    }
+
+    #[test]
+    fn test_lp_makespan_outweighs_priorities() {
+        use crate::job::Job;
+        use crate::scheduler::lp_scheduler::LPObjective;
+        use crate::scheduler::Schedule;
+
+        let cuboid = |sz| {
+            Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                Coordinate::new(0, 0, 0),
+                1,
+                1,
+                sz,
+            )]))
+        };
+        let jobs = vec![Job::new(0, 0, cuboid(3)), Job::new(1, 0, cuboid(1))];
+        let weights = vec![0., 100.];
+        let evaluate = |s: &[_]| LPObjective::Makespan.evaluate(&jobs, s, &weights, 0, 4);
+
+        // The high-priority program starts late, but the makespan is shorter.
+        let side_by_side = [
+            Schedule::new(0, 0, 0, 0, false),
+            Schedule::new(1, 0, 2, 0, false),
+        ];
+        let stacked = [
+            Schedule::new(0, 0, 1, 0, false),
+            Schedule::new(0, 0, 0, 0, false),
+        ];
+        assert!(evaluate(&side_by_side) < 4.);
+        assert!(evaluate(&stacked) >= 4.);
+    }
+
+    #[test]
+    fn test_lp_solver_backends() {
+        let solvers = [
+            LPSolver::Cbc,
+            LPSolver::Cplex,
+            LPSolver::Highs,
+            LPSolver::Minilp,
+        ];
+        for solver in solvers.into_iter().filter(|s| s.is_available()) {
+            let config = PackingConfig {
+                solver,
+                time_limit: Some(60),
+                size_x: 2,
+                size_y: 1,
+                min_z: 0,
+                max_z: 4,
+                dump: None,
+            };
+            let programs = vec![
+                vec![Cuboid::new(Coordinate::new(0, 0, 0), 1, 1, 2)],
+                vec![Cuboid::new(Coordinate::new(0, 0, 0), 1, 1, 2)],
+            ];
+            let schedules = CuboidPackingProblem::new(config, vec![], programs)
+                .solve()
+                .unwrap();
+            // The programs are placed side by side.
+            assert_eq!((schedules[0].z, schedules[1].z), (0, 0), "{:?}", solver);
+            assert_ne!(schedules[0].x, schedules[1].x, "{:?}", solver);
+        }
+    }
 }
@@ -0,0 +1,164 @@
+use rayon::prelude::*;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Evaluates placement candidates on a thread pool of `scheduler.num_threads` threads.
+///
+/// The best candidate is chosen by its key and then by its index, so the result does not
+/// depend on the number of threads.
+pub struct CandidateEvaluator {
+    /// `None` if the candidates are evaluated in the calling thread
+    pool: Option<rayon::ThreadPool>,
+    /// The number of candidates evaluated since the last `take_evaluated`
+    evaluated: AtomicU64,
+}
+
+impl CandidateEvaluator {
+    /// Creates an evaluator with `num_threads` threads. `None` or 1 means the sequential
+    /// evaluation, and 0 means the number of CPUs.
+    pub fn new(num_threads: Option<usize>) -> Self {
+        let pool = match num_threads {
+            None | Some(1) => None,
+            Some(n) => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(n)
+                    .build()
+                    .expect("failed to create a thread pool"),
+            ),
+        };
+        Self {
+            pool,
+            evaluated: AtomicU64::new(0),
+        }
+    }
+
+    pub fn sequential() -> Self {
+        Self {
+            pool: None,
+            evaluated: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of candidates evaluated since the last call, which is the work of
+    /// `SchedulingTimer` for schedulers placing programs by the evaluator.
+    pub fn take_evaluated(&self) -> u64 {
+        self.evaluated.swap(0, Ordering::Relaxed)
+    }
+
+    /// Evaluates the candidates `0..num_candidates` by `eval`, which returns `None` for
+    /// infeasible ones, and returns the index and the key of the best (= least) one. Ties are
+    /// broken by the least index.
+    pub fn min_by_key<K, F>(&self, num_candidates: usize, eval: F) -> Option<(usize, K)>
+    where
+        K: Ord + Send,
+        F: Fn(usize) -> Option<K> + Sync,
+    {
+        self.evaluated
+            .fetch_add(num_candidates as u64, Ordering::Relaxed);
+        let best = match &self.pool {
+            None => (0..num_candidates)
+                .filter_map(|i| eval(i).map(|key| (key, i)))
+                .min(),
+            Some(pool) => pool.install(|| {
+                (0..num_candidates)
+                    .into_par_iter()
+                    .filter_map(|i| eval(i).map(|key| (key, i)))
+                    .min()
+            }),
+        };
+        best.map(|(key, i)| (i, key))
+    }
+
+    /// Returns the least feasible candidate. Candidates are evaluated in order and the
+    /// evaluation stops at the first feasible one, although the thread pool may also evaluate
+    /// some candidates after it.
+    pub fn first<F>(&self, num_candidates: usize, is_feasible: F) -> Option<usize>
+    where
+        F: Fn(usize) -> bool + Sync,
+    {
+        let is_feasible = |i| {
+            self.evaluated.fetch_add(1, Ordering::Relaxed);
+            is_feasible(i)
+        };
+        match &self.pool {
+            None => (0..num_candidates).find(|&i| is_feasible(i)),
+            Some(pool) => pool.install(|| {
+                (0..num_candidates)
+                    .into_par_iter()
+                    .position_first(is_feasible)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CandidateEvaluator;
+    use crate::config::SimulationConfig;
+    use crate::environment::Environment;
+    use crate::job::Job;
+    use crate::program::{Coordinate, Cuboid, Program, ProgramFormat};
+    use crate::scheduler::corner_greedy_scheduler::place_jobs_at_corners;
+    use crate::test_utils;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_deterministic_tie_breaking() {
+        let keys = [5, 3, 7, 3, 3, 9, 3];
+        for num_threads in [None, Some(2), Some(4)] {
+            let evaluator = CandidateEvaluator::new(num_threads);
+            let best = evaluator.min_by_key(keys.len(), |i| (i != 1).then_some(keys[i]));
+            assert_eq!(best, Some((3, 3)));
+            assert_eq!(evaluator.first(keys.len(), |i| keys[i] > 6), Some(2));
+            assert_eq!(evaluator.first(keys.len(), |_| false), None);
+        }
+    }
+
+    #[test]
+    fn test_first_stops_at_feasible_candidate() {
+        let num_calls = AtomicU64::new(0);
+        let is_feasible = |i| {
+            num_calls.fetch_add(1, Ordering::Relaxed);
+            i % 100 == 42
+        };
+        let evaluator = CandidateEvaluator::sequential();
+        assert_eq!(evaluator.first(10000, is_feasible), Some(42));
+        assert_eq!(num_calls.swap(0, Ordering::Relaxed), 43);
+        assert_eq!(evaluator.take_evaluated(), 43);
+        assert_eq!(evaluator.first(100, |_| false), None);
+        assert_eq!(evaluator.min_by_key(100, Some), Some((0, 0)));
+        assert_eq!(evaluator.take_evaluated(), 200);
+        assert_eq!(evaluator.take_evaluated(), 0);
+
+        // The thread pool finds the same candidate and counts the candidates it evaluated.
+        let evaluator = CandidateEvaluator::new(Some(4));
+        assert_eq!(evaluator.first(10000, is_feasible), Some(42));
+        let num_calls = num_calls.into_inner();
+        assert!(num_calls >= 43);
+        assert_eq!(evaluator.take_evaluated(), num_calls);
+    }
+
+    #[test]
+    fn test_parallel_corner_greedy() {
+        let config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let env = Environment::new(config);
+        let jobs: Vec<_> = (0..8)
+            .map(|i| {
+                let size = 1 + i % 3;
+                let program = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+                    Coordinate::new(0, 0, 0),
+                    size,
+                    4 - size,
+                    1 + i % 2,
+                )]));
+                Job::new(i as u32, 0, program)
+            })
+            .collect();
+
+        let expected = place_jobs_at_corners(&env, &CandidateEvaluator::sequential(), 0, &jobs);
+        for num_threads in [2, 3, 8] {
+            let evaluator = CandidateEvaluator::new(Some(num_threads));
+            assert_eq!(place_jobs_at_corners(&env, &evaluator, 0, &jobs), expected);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::config::SchedulerConfig;
+use crate::error::QMPError;
+use crate::job::Job;
+use crate::scheduler::batching::{BatchRound, BatchSizer};
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// The tenant of the jobs requested without a tenant.
+pub const DEFAULT_TENANT: &str = "default";
+
+/// The policy to decide which waiting jobs enter each batch.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueuePolicy {
+    /// Jobs with higher priority (or earlier deadline) come first.
+    #[default]
+    Priority,
+    /// The next job is taken from the tenant with the least usage (the spacetime volume of the
+    /// jobs taken so far) relative to its share. The jobs of each tenant are taken in the order
+    /// of `Priority`.
+    FairShare,
+}
+
+/// The waiting jobs of a scheduler.
+pub struct JobQueue {
+    jobs: VecDeque<Job>,
+    policy: QueuePolicy,
+    /// The share of each tenant. Tenants not listed here have the share 1.
+    shares: BTreeMap<String, f64>,
+    /// The spacetime volume of the jobs taken from the queue for each tenant.
+    usage: BTreeMap<String, u64>,
+    batch_sizer: BatchSizer,
+}
+
+pub fn tenant_of(job: &Job) -> &str {
+    job.tenant.as_deref().unwrap_or(DEFAULT_TENANT)
+}
+
+/// Checks that the share of every tenant is positive.
+pub fn validate_tenant_shares(shares: &BTreeMap<String, f64>) -> anyhow::Result<()> {
+    match shares
+        .iter()
+        .find(|(_, share)| share.is_nan() || **share <= 0.0)
+    {
+        Some((tenant, share)) => Err(QMPError::InvalidTenantShare {
+            tenant: tenant.clone(),
+            share: *share,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+impl JobQueue {
+    pub fn new(config: &SchedulerConfig) -> Self {
+        // The shares are validated when the config is loaded.
+        let shares = config.tenant_shares.clone().unwrap_or_default();
+        Self {
+            jobs: VecDeque::new(),
+            policy: config.queue_policy.clone().unwrap_or_default(),
+            shares,
+            usage: BTreeMap::new(),
+            batch_sizer: BatchSizer::new(config),
+        }
+    }
+
+    pub fn push(&mut self, job: Job) {
+        self.jobs.push_back(job);
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    /// Takes at most `batch_size` jobs (all jobs if `None`) in the order given by the policy.
+    pub fn take(&mut self, batch_size: Option<u32>) -> Vec<Job> {
+        // Jobs with higher priority (or earlier deadline) come first. Since the sort is stable,
+        // jobs with the same priority are taken in FIFO order.
+        self.jobs
+            .make_contiguous()
+            .sort_by(|job1, job2| job2.cmp(job1));
+        let take_len = if let Some(batch_size) = batch_size {
+            usize::min(self.jobs.len(), batch_size as usize)
+        } else {
+            self.jobs.len()
+        };
+
+        let mut taken_jobs = Vec::new();
+        for _ in 0..take_len {
+            let i = match self.policy {
+                QueuePolicy::Priority => 0,
+                QueuePolicy::FairShare => self.next_fair_share_index(),
+            };
+            let job = self.jobs.remove(i).unwrap();
+            *self.usage.entry(tenant_of(&job).to_string()).or_default() += job.program.volume();
+            taken_jobs.push(job);
+        }
+        taken_jobs
+    }
+
+    /// Takes the jobs of the next batch, whose size is decided by `BatchSizer`.
+    pub fn take_batch(&mut self) -> Vec<Job> {
+        let batch_size = self.batch_sizer.next(self.jobs.len());
+        self.take(batch_size)
+    }
+
+    /// Reports the latency of the scheduling of the last batch to adapt the batch size.
+    pub fn observe_latency(&mut self, latency: u64, predicted: u64) {
+        self.batch_sizer.observe(latency, predicted);
+    }
+
+    pub fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        self.batch_sizer.rounds()
+    }
+
+    /// Returns the jobs taken but not scheduled to the head of the queue. They are not
+    /// counted in the usage of their tenants.
+    pub fn return_jobs(&mut self, jobs: Vec<Job>) {
+        for job in jobs.into_iter().rev() {
+            if let Some(usage) = self.usage.get_mut(tenant_of(&job)) {
+                *usage -= job.program.volume();
+            }
+            self.jobs.push_front(job);
+        }
+    }
+
+    /// Returns the index of the first job of the tenant with the least usage per share.
+    fn next_fair_share_index(&self) -> usize {
+        let normalized_usage = |tenant: &str| {
+            let usage = self.usage.get(tenant).copied().unwrap_or(0);
+            usage as f64 / self.shares.get(tenant).copied().unwrap_or(1.0)
+        };
+        let mut best: Option<(usize, f64)> = None;
+        for (i, job) in self.jobs.iter().enumerate() {
+            let usage = normalized_usage(tenant_of(job));
+            if best.is_none_or(|(_, best_usage)| usage < best_usage) {
+                best = Some((i, usage));
+            }
+        }
+        best.unwrap().0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate_tenant_shares, JobQueue, QueuePolicy};
+    use crate::config::SimulationConfig;
+    use crate::job::Job;
+    use crate::program::{Coordinate, Cuboid, Program, ProgramFormat};
+    use crate::test_utils;
+
+    #[test]
+    fn test_fair_share_queue() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.scheduler.queue_policy = Some(QueuePolicy::FairShare);
+        config.scheduler.tenant_shares = Some([("a".to_string(), 2.0)].into_iter().collect());
+        let mut queue = JobQueue::new(&config.scheduler);
+
+        let program = Program::new(ProgramFormat::Cuboid(vec![Cuboid::new(
+            Coordinate::new(0, 0, 0),
+            1,
+            1,
+            1,
+        )]));
+        // Tenant "a" requests all jobs first and has twice the share of "b".
+        for id in 0..6 {
+            let tenant = if id < 4 { "a" } else { "b" };
+            queue.push(Job::new(id, id as u64, program.clone()).with_tenant(Some(tenant.into())));
+        }
+
+        let taken: Vec<_> = queue.take(Some(3)).iter().map(|job| job.id).collect();
+        assert_eq!(taken, vec![0, 4, 1]);
+        let taken = queue.take(Some(1));
+        assert_eq!(taken[0].id, 2);
+
+        // The returned job is not counted in the usage.
+        queue.return_jobs(taken);
+        assert_eq!(queue.len(), 3);
+        let taken: Vec<_> = queue.take(None).iter().map(|job| job.id).collect();
+        assert_eq!(taken, vec![2, 5, 3]);
+    }
+
+    #[test]
+    fn test_validate_tenant_shares() {
+        let shares = |share: f64| [("a".to_string(), 1.0), ("b".to_string(), share)].into();
+        assert!(validate_tenant_shares(&shares(0.5)).is_ok());
+        for share in [0.0, -1.0, f64::NAN] {
+            assert!(validate_tenant_shares(&shares(share)).is_err());
+        }
+    }
+}
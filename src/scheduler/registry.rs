@@ -0,0 +1,193 @@
+use crate::config::SimulationConfig;
+use crate::error::QMPError;
+use crate::scheduler::{
+    AnnealingScheduler, BackfillingScheduler, BeamSearchScheduler, CornerGreedyScheduler,
+    GreedyScheduler, LPScheduler, Scheduler, SkylineScheduler,
+};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+
+/// The free-form `[scheduler.params]` table of the config file.
+pub type SchedulerParams = toml::Table;
+
+/// Creates a scheduler from the simulation config and the `[scheduler.params]` table.
+pub type SchedulerFactory =
+    Box<dyn Fn(SimulationConfig, &SchedulerParams) -> Result<Box<dyn Scheduler>> + Send + Sync>;
+
+/// A set of schedulers looked up by the `kind` in the config file.
+///
+/// `SchedulerRegistry::default()` contains the built-in schedulers. Schedulers implemented in
+/// other crates can be added by `register`:
+///
+/// ```ignore
+/// let mut registry = SchedulerRegistry::default();
+/// registry.register("my-scheduler", |config, params| {
+///     Ok(Box::new(MyScheduler::new(config, deserialize_params(params)?)))
+/// })?;
+/// let scheduler = registry.create(&config)?;
+/// ```
+pub struct SchedulerRegistry {
+    factories: BTreeMap<String, SchedulerFactory>,
+}
+
+impl SchedulerRegistry {
+    /// Returns a registry without any scheduler.
+    pub fn empty() -> Self {
+        Self {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a scheduler under `name`. Returns an error if the name is already used.
+    pub fn register<F>(&mut self, name: &str, factory: F) -> Result<()>
+    where
+        F: Fn(SimulationConfig, &SchedulerParams) -> Result<Box<dyn Scheduler>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        if self.factories.contains_key(name) {
+            return Err(QMPError::DuplicateScheduler(name.to_string()).into());
+        }
+        self.factories.insert(name.to_string(), Box::new(factory));
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    /// Returns the registered names in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(|name| name.as_str())
+    }
+
+    /// Creates the scheduler specified by `config.scheduler.kind`.
+    pub fn create(&self, config: &SimulationConfig) -> Result<Box<dyn Scheduler>> {
+        let name = &config.scheduler.kind;
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| QMPError::UnknownScheduler {
+                name: name.clone(),
+                available: self.names().collect::<Vec<_>>().join(", "),
+            })?;
+        factory(config.clone(), &config.scheduler.params)
+    }
+}
+
+impl Default for SchedulerRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        macro_rules! register_builtin {
+            ($name:expr, $scheduler:ident) => {
+                registry
+                    .register($name, |config, _| Ok(Box::new($scheduler::new(config))))
+                    .unwrap()
+            };
+        }
+        register_builtin!("greedy", GreedyScheduler);
+        register_builtin!("cornergreedy", CornerGreedyScheduler);
+        register_builtin!("lp", LPScheduler);
+        register_builtin!("skyline", SkylineScheduler);
+        register_builtin!("annealing", AnnealingScheduler);
+        register_builtin!("beamsearch", BeamSearchScheduler);
+        register_builtin!("backfilling", BackfillingScheduler);
+        registry
+    }
+}
+
+/// Deserializes the `[scheduler.params]` table into the parameters of a scheduler.
+pub fn deserialize_params<T: DeserializeOwned>(params: &SchedulerParams) -> Result<T> {
+    Ok(params.clone().try_into()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deserialize_params, SchedulerRegistry};
+    use crate::config::SimulationConfig;
+    use crate::environment::Environment;
+    use crate::job::{Job, JobID};
+    use crate::program::{Program, ProgramFormat};
+    use crate::scheduler::{GreedyScheduler, Schedule, Scheduler};
+    use crate::test_utils;
+
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct FixedParams {
+        z: i32,
+    }
+
+    /// Places every job at the origin of the given z position.
+    struct FixedScheduler {
+        z: i32,
+        jobs: Vec<JobID>,
+    }
+
+    impl Scheduler for FixedScheduler {
+        fn add_job(&mut self, job: Job) {
+            self.jobs.push(job.id);
+        }
+
+        fn run(&mut self, _env: &Environment) -> Vec<(JobID, Schedule)> {
+            self.jobs
+                .drain(..)
+                .map(|id| (id, Schedule::new(0, 0, self.z, 0, false)))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_scheduler_registry() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        let mut registry = SchedulerRegistry::default();
+        let names: Vec<_> = registry.names().collect();
+        assert_eq!(
+            names,
+            vec![
+                "annealing",
+                "backfilling",
+                "beamsearch",
+                "cornergreedy",
+                "greedy",
+                "lp",
+                "skyline"
+            ]
+        );
+        assert!(registry.create(&config).is_ok());
+
+        registry
+            .register("fixed", |_, params| {
+                let params: FixedParams = deserialize_params(params)?;
+                Ok(Box::new(FixedScheduler {
+                    z: params.z,
+                    jobs: Vec::new(),
+                }))
+            })
+            .unwrap();
+        assert!(registry
+            .register("greedy", |config, _| Ok(Box::new(GreedyScheduler::new(
+                config
+            ))))
+            .is_err());
+
+        config.scheduler.kind = "fixed".to_string();
+        config.scheduler.params = toml::from_str("z = 5").unwrap();
+        let mut scheduler = registry.create(&config).unwrap();
+        let program = Program::new(ProgramFormat::Cuboid(vec![]));
+        scheduler.add_job(Job::new(0, 0, program));
+        let res = scheduler.run(&Environment::new(config.clone()));
+        assert_eq!(res, vec![(0, Schedule::new(0, 0, 5, 0, false))]);
+
+        // missing parameter
+        config.scheduler.params = toml::Table::new();
+        assert!(registry.create(&config).is_err());
+
+        config.scheduler.kind = "unknown".to_string();
+        let err = registry.create(&config).err().unwrap();
+        assert!(err.to_string().contains("unknown"));
+    }
+}
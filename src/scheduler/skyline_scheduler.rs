@@ -0,0 +1,233 @@
+use crate::config::SimulationConfig;
+use crate::environment::Environment;
+use crate::job::Job;
+use crate::program::{Cuboid, ProgramFormat};
+use crate::scheduler::{
+    apply_schedule, BatchRound, JobID, JobQueue, LatencyPredictor, LatencyRound, Schedule,
+    Scheduler, SchedulingTimer,
+};
+
+use std::collections::VecDeque;
+
+/// The height map of the chip, i.e., the earliest free z position of each (x, y) position.
+#[derive(Debug, Clone)]
+struct HeightMap {
+    size_x: usize,
+    size_y: usize,
+    heights: Vec<i32>, // (x, y) -> heights[x * size_y + y]
+}
+
+impl HeightMap {
+    fn new(size_x: usize, size_y: usize, base: i32) -> Self {
+        Self {
+            size_x,
+            size_y,
+            heights: vec![base; size_x * size_y],
+        }
+    }
+
+    /// Creates the height map from running programs and defragmentation moves in `env`.
+    /// All positions below `base` are regarded as occupied.
+    fn from_env(env: &Environment, size_x: usize, size_y: usize, base: i32) -> Self {
+        let mut height_map = Self::new(size_x, size_y, base);
+        for program in env.running_programs().iter() {
+            match program.format() {
+                ProgramFormat::Polycube(p) => {
+                    for b in p.blocks() {
+                        height_map.raise(b.x as usize, b.y as usize, b.z + 1);
+                    }
+                }
+                ProgramFormat::Cuboid(cs) => {
+                    for c in cs {
+                        height_map.raise_area(c, c.z2());
+                    }
+                }
+            }
+        }
+        // A program must not be executed while other programs move across its area.
+        for move_area in env.defrag_move_areas() {
+            if move_area.z1() > base {
+                height_map.raise_area(move_area, move_area.z1());
+            }
+        }
+        height_map
+    }
+
+    fn height(&self, x: usize, y: usize) -> i32 {
+        self.heights[x * self.size_y + y]
+    }
+
+    fn raise(&mut self, x: usize, y: usize, z: i32) {
+        let h = &mut self.heights[x * self.size_y + y];
+        *h = i32::max(*h, z);
+    }
+
+    fn raise_area(&mut self, c: &Cuboid, z: i32) {
+        for x in c.x1()..c.x2() {
+            for y in c.y1()..c.y2() {
+                self.raise(x as usize, y as usize, z);
+            }
+        }
+    }
+
+    /// Returns the lowest position (x, y, z) where a box of the size `w * d` can be placed.
+    /// Ties are broken by the smaller x + y.
+    ///
+    /// This runs in O(size_x * size_y) time using sliding window maximums.
+    fn lowest_position(&self, w: usize, d: usize) -> Option<(i32, i32, i32)> {
+        if w == 0 || d == 0 || w > self.size_x || d > self.size_y {
+            return None;
+        }
+        let ny = self.size_y - d + 1;
+
+        // max over [y, y + d) for each x
+        let mut max_y = vec![0; self.size_x * ny];
+        for x in 0..self.size_x {
+            let row: Vec<_> = (0..self.size_y).map(|y| self.height(x, y)).collect();
+            for (y, h) in sliding_window_max(&row, d).into_iter().enumerate() {
+                max_y[x * ny + y] = h;
+            }
+        }
+
+        let mut best: Option<(i32, i32, i32)> = None;
+        for y in 0..ny {
+            let column: Vec<_> = (0..self.size_x).map(|x| max_y[x * ny + y]).collect();
+            for (x, z) in sliding_window_max(&column, w).into_iter().enumerate() {
+                let (x, y) = (x as i32, y as i32);
+                if best.is_none_or(|(bx, by, bz)| (z, x + y) < (bz, bx + by)) {
+                    best = Some((x, y, z));
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Returns the maximum values of all windows of the length `len` in `values`.
+fn sliding_window_max(values: &[i32], len: usize) -> Vec<i32> {
+    let mut deque: VecDeque<usize> = VecDeque::new();
+    let mut result = Vec::with_capacity(values.len() + 1 - len);
+    for (i, v) in values.iter().enumerate() {
+        while deque.back().is_some_and(|&j| values[j] <= *v) {
+            deque.pop_back();
+        }
+        deque.push_back(i);
+        if deque[0] + len <= i {
+            deque.pop_front();
+        }
+        if i + 1 >= len {
+            result.push(values[deque[0]]);
+        }
+    }
+    result
+}
+
+/// A scheduler that places the bounding box of each program at the lowest position of the
+/// height map of the chip. Since it never places programs below other programs, the scheduling
+/// takes only O(size_x * size_y) time per program.
+pub struct SkylineScheduler {
+    job_list: JobQueue,
+    config: SimulationConfig,
+    latency_predictor: LatencyPredictor,
+    timer: SchedulingTimer,
+}
+
+impl SkylineScheduler {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self {
+            job_list: JobQueue::new(&config.scheduler),
+            latency_predictor: LatencyPredictor::new(config.scheduler.latency_predictor.clone()),
+            timer: SchedulingTimer::new(&config),
+            config,
+        }
+    }
+}
+
+impl Scheduler for SkylineScheduler {
+    fn add_job(&mut self, job: Job) {
+        self.job_list.push(job);
+    }
+
+    fn run(&mut self, env: &Environment) -> Vec<(JobID, Schedule)> {
+        let est_scheduling_cost = self.latency_predictor.predict();
+
+        self.timer.start();
+
+        let scheduled_point = (env.global_pc() + est_scheduling_cost) as i32;
+        let size_x = self.config.size_x as usize;
+        let size_y = self.config.size_y as usize;
+        let mut height_map = HeightMap::from_env(env, size_x, size_y, scheduled_point);
+
+        let mut res = Vec::new();
+        for job in self.job_list.take_batch() {
+            let mut best: Option<(Schedule, Cuboid)> = None;
+            for rot in 0..2 {
+                let bbox = apply_schedule(&job.program, &Schedule::new(0, 0, 0, rot, false))
+                    .bounding_box();
+                // The work is the number of positions compared.
+                let num_positions = (size_x + 1).saturating_sub(bbox.size_x())
+                    * (size_y + 1).saturating_sub(bbox.size_y());
+                self.timer.add_work(num_positions as u64);
+                if let Some((x, y, z)) = height_map.lowest_position(bbox.size_x(), bbox.size_y()) {
+                    if best.as_ref().is_none_or(|(_, placed)| {
+                        (z, x + y) < (placed.z1(), placed.x1() + placed.y1())
+                    }) {
+                        let schedule =
+                            Schedule::new(x - bbox.x1(), y - bbox.y1(), z - bbox.z1(), rot, false);
+                        let placed = apply_schedule(&job.program, &schedule).bounding_box();
+                        best = Some((schedule, placed));
+                    }
+                }
+            }
+
+            let (schedule, placed) = best.expect("The program is larger than the chip");
+            height_map.raise_area(&placed, placed.z2());
+            res.push((job.id, schedule));
+        }
+
+        let elapsed = self.timer.stop(res.len());
+        self.latency_predictor.observe(elapsed);
+        self.job_list.observe_latency(elapsed, est_scheduling_cost);
+
+        res
+    }
+
+    fn latency_rounds(&self) -> Option<&[LatencyRound]> {
+        Some(self.latency_predictor.rounds())
+    }
+
+    fn scheduling_cycles(&self) -> Option<u64> {
+        self.timer.last_cycles()
+    }
+
+    fn batch_rounds(&self) -> Option<&[BatchRound]> {
+        self.job_list.batch_rounds()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sliding_window_max, HeightMap};
+    use crate::program::{Coordinate, Cuboid};
+
+    #[test]
+    fn test_sliding_window_max() {
+        let values = [1, 3, 2, 0, 5, 1];
+        assert_eq!(sliding_window_max(&values, 1), values.to_vec());
+        assert_eq!(sliding_window_max(&values, 2), vec![3, 3, 2, 5, 5]);
+        assert_eq!(sliding_window_max(&values, 3), vec![3, 3, 5, 5]);
+        assert_eq!(sliding_window_max(&values, 6), vec![5]);
+    }
+
+    #[test]
+    fn test_height_map_lowest_position() {
+        let mut height_map = HeightMap::new(3, 3, 0);
+        height_map.raise_area(&Cuboid::new(Coordinate::new(0, 0, 0), 2, 2, 4), 4);
+        height_map.raise_area(&Cuboid::new(Coordinate::new(2, 0, 0), 1, 3, 2), 2);
+
+        assert_eq!(height_map.lowest_position(1, 1), Some((0, 2, 0)));
+        assert_eq!(height_map.lowest_position(1, 3), Some((2, 0, 2)));
+        assert_eq!(height_map.lowest_position(3, 3), Some((0, 0, 4)));
+        assert_eq!(height_map.lowest_position(4, 1), None);
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::config::SimulationConfig;
+
+/// How the latency of a scheduling is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SchedulingTimeMode {
+    /// The elapsed wall-clock time converted by `micro_sec_per_cycle`
+    #[default]
+    WallClock,
+    /// The cost computed by `SchedulingCostModel` from the work done by the scheduler, which
+    /// makes the simulation reproducible on any machine
+    Modelled,
+}
+
+/// The latency of a scheduling in the `modelled` mode is
+/// `base_cycles + cycles_per_job * (batch size) + ceil(cycles_per_work * (work))`, where the
+/// work is counted by each scheduler as follows:
+/// * `annealing`: the number of iterations
+/// * `beamsearch`: the number of expanded states
+/// * `lp`: the number of candidates evaluated by the corner greedy algorithm plus the number of
+///   variables of the model
+/// * `skyline`: the number of positions compared
+/// * the others: the number of placement candidates evaluated
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchedulingCostModel {
+    /// 0 by default
+    pub base_cycles: Option<u64>,
+    /// 1 by default
+    pub cycles_per_job: Option<u64>,
+    /// 0 by default
+    pub cycles_per_work: Option<f64>,
+}
+
+impl SchedulingCostModel {
+    pub fn cycles(&self, num_jobs: usize, work: u64) -> u64 {
+        let work_cycles = (self.cycles_per_work.unwrap_or(0.0) * work as f64).ceil() as u64;
+        self.base_cycles.unwrap_or(0)
+            + self.cycles_per_job.unwrap_or(1) * num_jobs as u64
+            + work_cycles
+    }
+}
+
+/// Measures the latency of each scheduling in cycles.
+pub struct SchedulingTimer {
+    mode: SchedulingTimeMode,
+    cost_model: SchedulingCostModel,
+    micro_sec_per_cycle: u64,
+    start: Instant,
+    work: u64,
+    last_cycles: Option<u64>,
+}
+
+impl SchedulingTimer {
+    pub fn new(config: &SimulationConfig) -> Self {
+        Self {
+            mode: config.scheduling_time.unwrap_or_default(),
+            cost_model: config.scheduling_cost.clone().unwrap_or_default(),
+            micro_sec_per_cycle: config.micro_sec_per_cycle,
+            start: Instant::now(),
+            work: 0,
+            last_cycles: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.start = Instant::now();
+        self.work = 0;
+    }
+
+    /// Counts the work done by the scheduler, which is used in the `modelled` mode.
+    pub fn add_work(&mut self, work: u64) {
+        self.work += work;
+    }
+
+    /// Returns the latency in cycles of the scheduling of `num_jobs` jobs since `start`.
+    pub fn stop(&mut self, num_jobs: usize) -> u64 {
+        let cycles = match self.mode {
+            SchedulingTimeMode::WallClock => {
+                self.start
+                    .elapsed()
+                    .as_micros()
+                    .div_ceil(self.micro_sec_per_cycle.into()) as u64
+            }
+            SchedulingTimeMode::Modelled => self.cost_model.cycles(num_jobs, self.work),
+        };
+        self.last_cycles = Some(cycles);
+        cycles
+    }
+
+    /// Returns the latency of the last scheduling in cycles.
+    pub fn last_cycles(&self) -> Option<u64> {
+        self.last_cycles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SchedulingCostModel, SchedulingTimeMode, SchedulingTimer};
+    use crate::config::SimulationConfig;
+    use crate::test_utils;
+
+    #[test]
+    fn test_modelled_scheduling_time() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.scheduling_time = Some(SchedulingTimeMode::Modelled);
+        config.scheduling_cost = Some(SchedulingCostModel {
+            base_cycles: Some(2),
+            cycles_per_job: Some(3),
+            cycles_per_work: Some(0.25),
+        });
+        let mut timer = SchedulingTimer::new(&config);
+        assert_eq!(timer.last_cycles(), None);
+
+        timer.start();
+        timer.add_work(5);
+        timer.add_work(4);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        // 2 + 3 * 2 + ceil(0.25 * 9)
+        assert_eq!(timer.stop(2), 11);
+        assert_eq!(timer.last_cycles(), Some(11));
+
+        timer.start();
+        assert_eq!(timer.stop(1), 5);
+    }
+}
@@ -1,7 +1,9 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::Instant;
 
+use crate::bounds::LowerBounds;
 use crate::config::SimulationConfig;
 use crate::dataset::Dataset;
 use crate::environment::Environment;
@@ -10,7 +12,9 @@ use crate::event::{Event, EventQueue, EventType};
 use crate::job::{Job, JobID, JobStatus};
 use crate::preprocess::{ConvertToCuboid, PreprocessKind, Preprocessor};
 use crate::program::{is_overlap, Program};
-use crate::scheduler::{apply_schedule, Schedule, Scheduler};
+use crate::scheduler::queue::tenant_of;
+use crate::scheduler::timing::SchedulingTimeMode;
+use crate::scheduler::{apply_schedule, BatchRound, LatencyRound, Schedule, Scheduler};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IssuedJob {
@@ -18,8 +22,27 @@ pub struct IssuedJob {
     program: Option<Program>,
     schedule: Schedule,
     requested_time: u64,
+    /// The cycles from the request to the scheduling, including the delay by the batching window
     waiting_time: u64,
     turnaround_time: u64,
+    priority: i32,
+    deadline: Option<u64>,
+    tenant: Option<String>,
+    /// The time when the execution of this job is completed.
+    completion_time: u64,
+    /// Whether this job is completed by the deadline (`None` if the deadline is unset).
+    deadline_met: Option<bool>,
+}
+
+/// The resource usage of a tenant.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TenantUsage {
+    pub num_jobs: u64,
+    /// The summation of the spacetime volume of the programs
+    pub volume: u64,
+    pub waiting_time_sum: u64,
+    pub avg_waiting_time: u64,
+    pub max_waiting_time: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +58,22 @@ pub struct SimulationResult {
     pub avg_response_time: u64,
     /// the summation of defragmentation_cost in code cycles
     pub defrag_cost_sum: Option<u64>,
+    /// The number of jobs completed by their deadlines
+    pub deadline_hits: u64,
+    /// The number of jobs completed after their deadlines
+    pub deadline_misses: u64,
+    /// Offline lower bounds on `total_cycle`
+    pub lower_bounds: LowerBounds,
+    /// `total_cycle` divided by the lower bound on it
+    pub competitive_ratio: Option<f64>,
+    /// The usage of each tenant (jobs without a tenant are counted as `default`)
+    pub tenant_usage: BTreeMap<String, TenantUsage>,
+    /// The predicted and actual latency of each scheduling
+    pub latency_rounds: Option<Vec<LatencyRound>>,
+    /// The batch size chosen in each scheduling (only with the adaptive batch size)
+    pub batch_rounds: Option<Vec<BatchRound>>,
+    /// Scheduler-specific statistics
+    pub scheduler_report: Option<serde_json::Value>,
 }
 
 pub struct Simulator {
@@ -49,6 +88,7 @@ pub struct Simulator {
     event_que: EventQueue,
     /// the event log
     event_log: Vec<Event>,
+    lower_bounds: LowerBounds,
 }
 
 impl Simulator {
@@ -65,15 +105,20 @@ impl Simulator {
             })
             .collect();
 
+        let lower_bounds = LowerBounds::new(&config, &dataset);
+
         let mut job_list = Vec::new();
         let mut event_que = EventQueue::new();
-        for (i, (t, p)) in dataset.requests().into_iter().enumerate() {
+        for (i, (req, p)) in dataset.requests().into_iter().enumerate() {
             let p = preprocessors
                 .iter()
                 .fold(p.clone(), |p, proc| proc.process(p));
-            let job = Job::new(i as JobID, t, p.clone());
+            let job = Job::new(i as JobID, req.time, p.clone())
+                .with_priority(req.priority)
+                .with_deadline(req.deadline)
+                .with_tenant(req.tenant.clone());
             job_list.push(job);
-            event_que.add_event(Event::request_job(t, i as JobID));
+            event_que.add_event(Event::request_job(req.time, i as JobID));
         }
         // add initial scheduling point
         event_que.add_event(Event::start_scheduling(0));
@@ -86,6 +131,7 @@ impl Simulator {
             simulation_time: 0,
             event_que,
             event_log: Vec::new(),
+            lower_bounds,
         }
     }
 
@@ -94,6 +140,7 @@ impl Simulator {
 
         let mut z_sum = 0;
         let mut response_time = Vec::new();
+        let mut tenant_usage: BTreeMap<String, TenantUsage> = BTreeMap::new();
 
         while let Some(event) = self.event_que.pop() {
             // If all jobs have been scheduled, we ignore the remaining event because they does not
@@ -121,6 +168,12 @@ impl Simulator {
                     self.scheduler.add_job(self.job_list[job_id].clone());
                 }
                 EventType::StartScheduling => {
+                    if let Some(wake_time) = self.batching_delay() {
+                        tracing::debug!("Scheduling is delayed until {}", wake_time);
+                        self.event_que.add_event(Event::start_scheduling(wake_time));
+                        continue;
+                    }
+
                     let start = Instant::now();
 
                     if self.config.enable_defrag {
@@ -129,8 +182,26 @@ impl Simulator {
                     let issued_programs = self.scheduler.run(&self.env);
                     let has_scheduled = !issued_programs.is_empty();
 
-                    let elapsed_msec = start.elapsed().as_micros() as u64;
-                    let elapsed_cycles = elapsed_msec.div_ceil(self.config.micro_sec_per_cycle);
+                    let (elapsed_msec, elapsed_cycles) =
+                        match self.config.scheduling_time.unwrap_or_default() {
+                            SchedulingTimeMode::WallClock => {
+                                let elapsed_msec = start.elapsed().as_micros() as u64;
+                                let elapsed_cycles =
+                                    elapsed_msec.div_ceil(self.config.micro_sec_per_cycle);
+                                (elapsed_msec, elapsed_cycles)
+                            }
+                            SchedulingTimeMode::Modelled => {
+                                // Schedulers without `SchedulingTimer` cost only per job.
+                                let elapsed_cycles =
+                                    self.scheduler.scheduling_cycles().unwrap_or_else(|| {
+                                        let cost_model =
+                                            self.config.scheduling_cost.clone().unwrap_or_default();
+                                        cost_model.cycles(issued_programs.len(), 0)
+                                    });
+                                let elapsed_msec = elapsed_cycles * self.config.micro_sec_per_cycle;
+                                (elapsed_msec, elapsed_cycles)
+                            }
+                        };
 
                     // If the current job que is empty, then the scheduler waits until the next
                     // event will occur
@@ -166,9 +237,9 @@ impl Simulator {
                             return Err(QMPError::invalid_job_id(job_id));
                         }
                         let scheduled_program = apply_schedule(&job.program, &schedule);
-                        if !self.env.issue_program(&scheduled_program) {
+                        if !self.env.issue_job(job_id, &scheduled_program) {
                             tracing::error!("scheduled program: {:?}", scheduled_program);
-                            for p in self.env.running_programs() {
+                            for p in self.env.running_programs().iter() {
                                 if is_overlap(&scheduled_program, p) {
                                     tracing::error!("overlap with {:?}", p);
                                 }
@@ -178,6 +249,11 @@ impl Simulator {
 
                         let waiting_time = self.simulation_time - job.requested_time;
                         let turnaround_time = waiting_time + scheduled_program.burst_time();
+                        let usage = tenant_usage.entry(tenant_of(job).to_string()).or_default();
+                        usage.num_jobs += 1;
+                        usage.volume += scheduled_program.volume();
+                        usage.waiting_time_sum += waiting_time;
+                        usage.max_waiting_time = usage.max_waiting_time.max(waiting_time);
                         let issued_job = IssuedJob {
                             job_id: job.id,
                             program: if self.config.no_output_program {
@@ -189,6 +265,11 @@ impl Simulator {
                             requested_time: job.requested_time,
                             waiting_time,
                             turnaround_time,
+                            priority: job.priority,
+                            deadline: job.deadline,
+                            tenant: job.tenant.clone(),
+                            completion_time: 0, // updated after the simulation
+                            deadline_met: None,
                         };
                         result.push(issued_job);
                         self.job_list[job_id as usize].update_status(JobStatus::Scheduled);
@@ -233,6 +314,26 @@ impl Simulator {
 
         let avg_response_time = response_time.iter().sum::<u64>() / (response_time.len() as u64);
 
+        for usage in tenant_usage.values_mut() {
+            usage.avg_waiting_time = usage.waiting_time_sum / usage.num_jobs;
+        }
+
+        let mut deadline_hits = 0;
+        let mut deadline_misses = 0;
+        // Programs may have been moved by defragmentations after they were issued.
+        let end_pcs = self.env.job_end_pcs();
+        for issued_job in result.iter_mut() {
+            issued_job.completion_time = self.env.time_at_pc(end_pcs[&issued_job.job_id]);
+            issued_job.deadline_met = issued_job
+                .deadline
+                .map(|deadline| issued_job.completion_time <= deadline);
+            match issued_job.deadline_met {
+                Some(true) => deadline_hits += 1,
+                Some(false) => deadline_misses += 1,
+                None => {}
+            }
+        }
+
         Ok(SimulationResult {
             event_log: self.event_log,
             jobs: result,
@@ -246,10 +347,81 @@ impl Simulator {
             } else {
                 None
             },
+            deadline_hits,
+            deadline_misses,
+            competitive_ratio: self.lower_bounds.ratio(self.simulation_time),
+            lower_bounds: self.lower_bounds,
+            tenant_usage,
+            latency_rounds: self
+                .scheduler
+                .latency_rounds()
+                .map(|rounds| rounds.to_vec()),
+            batch_rounds: self.scheduler.batch_rounds().map(|rounds| rounds.to_vec()),
+            scheduler_report: self.scheduler.report(),
         })
     }
 
+    /// Returns the time when the scheduling should start if it is delayed by the batching window.
+    fn batching_delay(&self) -> Option<u64> {
+        let window = self.config.batching_window.as_ref()?;
+        let waiting: Vec<_> = self
+            .job_list
+            .iter()
+            .filter(|job| {
+                job.status() == &JobStatus::Waiting && job.requested_time <= self.simulation_time
+            })
+            .collect();
+        let oldest = waiting.iter().map(|job| job.requested_time).min()?;
+        if window.num_jobs.is_some_and(|n| waiting.len() >= n as usize) {
+            return None;
+        }
+        let deadline = window.max_wait.map(|max_wait| oldest + max_wait);
+        if deadline.is_some_and(|deadline| deadline <= self.simulation_time) {
+            return None;
+        }
+        // Wait for the next request (which may fill the batch) or the deadline.
+        [self.event_que.next_event_time(), deadline]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
     pub fn log_event(&mut self, event: Event) {
         self.event_log.push(event)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Simulator;
+    use crate::config::{BatchingWindow, SimulationConfig};
+    use crate::dataset::Dataset;
+    use crate::scheduler::timing::SchedulingTimeMode;
+    use crate::scheduler::GreedyScheduler;
+    use crate::test_utils;
+
+    #[test]
+    fn test_batching_window() {
+        let mut config = SimulationConfig::from_toml(test_utils::TEST_TOML_FILE.into()).unwrap();
+        config.preprocessor.processes.clear();
+        config.scheduling_time = Some(SchedulingTimeMode::Modelled);
+        config.batching_window = Some(BatchingWindow {
+            num_jobs: Some(3),
+            max_wait: Some(10),
+        });
+        let dataset: Dataset = serde_json::from_str(
+            r#"{
+                "programs": [{"Cuboid": [{"pos": [0, 0, 0], "size_x": 1, "size_y": 1, "size_z": 1}]}],
+                "job_requests": [[0, 0], [2, 0], [30, 0], [31, 0], [32, 0]]
+            }"#,
+        )
+        .unwrap();
+        let scheduler = Box::new(GreedyScheduler::new(config.clone()));
+        let result = Simulator::new(config, dataset, scheduler).run().unwrap();
+
+        // The first two jobs wait until 10 cycles after the first request, and the others are
+        // scheduled as soon as the third one is requested.
+        let waiting_times: Vec<_> = result.jobs.iter().map(|job| job.waiting_time).collect();
+        assert_eq!(waiting_times, vec![10, 8, 2, 1, 0]);
+    }
+}